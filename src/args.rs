@@ -1,6 +1,12 @@
 use clap::Parser;
 
-use crate::constants::{MOLECULE_DEFAULT_ADDR, MOLECULE_DEFAULT_PORT};
+use crate::config::Config;
+use crate::constants::{
+    MOLECULE_DEFAULT_ADDR, MOLECULE_DEFAULT_CACHE_SIZE, MOLECULE_DEFAULT_COMPACTION_INTERVAL_SECS,
+    MOLECULE_DEFAULT_MAX_FRAME_SIZE, MOLECULE_DEFAULT_PORT,
+    MOLECULE_DEFAULT_TTL_SWEEP_INTERVAL_SECS,
+};
+use crate::core::storage::StorageEngine;
 
 /// Majestic Rust-native SQL Database.
 #[derive(Parser, Debug)]
@@ -12,9 +18,45 @@ pub struct Args {
     /// Port to bind to, defaults to `80`
     #[arg(short, long)]
     pub port: Option<u32>,
+    /// Directory all data is stored under, defaults to `.molecule` in the working directory. Only
+    /// one server can use a data directory at a time.
+    #[arg(long)]
+    pub data_dir: Option<String>,
+    /// JSON file to read options from, which the other arguments take precedence over.
+    #[arg(long)]
+    pub config: Option<String>,
     /// Provide a string formatted `username:password` to use in the database auth gate.
     #[arg(long)]
     pub auth: Option<String>,
+    /// Largest TCP frame payload in bytes accepted from clients, defaults to 16 MiB.
+    #[arg(long)]
+    pub max_frame_size: Option<usize>,
+    /// Seconds between sweeps removing records expired by TTL indexes, defaults to 60.
+    #[arg(long)]
+    pub ttl_sweep_interval: Option<u64>,
+    /// Seconds between checks for collections worth compacting, defaults to 300.
+    #[arg(long)]
+    pub compaction_interval: Option<u64>,
+    /// Memory budget in bytes for collections cached in memory, defaults to 256 MiB. Least
+    /// recently used collections are evicted once it's exceeded.
+    #[arg(long)]
+    pub cache_size: Option<usize>,
+    /// Storage engine for a new data directory, defaults to `segment`. Existing data
+    /// directories keep the engine they were created with.
+    #[arg(long, value_enum)]
+    pub storage_engine: Option<StorageEngine>,
+    /// Keep every collection in memory only, without touching the data directory. Nothing is
+    /// kept once the database stops.
+    #[arg(long, conflicts_with_all = ["storage_engine", "data_dir"])]
+    pub in_memory: bool,
+    /// Allow running without auth on an address reachable from other machines. Without a user set
+    /// up with `--auth`, the database otherwise refuses to listen on anything but loopback.
+    #[arg(long)]
+    pub allow_unauthenticated: bool,
+    /// Repair inconsistencies found in the data directory on startup instead of refusing to
+    /// start.
+    #[arg(long)]
+    pub repair: bool,
     /// Run the CLI for molecule along with the database.
     #[arg(long)]
     pub cli: bool,
@@ -27,9 +69,59 @@ impl Default for Args {
         Self {
             addr: Some(MOLECULE_DEFAULT_ADDR.to_string()),
             port: Some(MOLECULE_DEFAULT_PORT),
+            data_dir: None,
+            config: None,
             auth: None,
+            max_frame_size: Some(MOLECULE_DEFAULT_MAX_FRAME_SIZE),
+            ttl_sweep_interval: Some(MOLECULE_DEFAULT_TTL_SWEEP_INTERVAL_SECS),
+            compaction_interval: Some(MOLECULE_DEFAULT_COMPACTION_INTERVAL_SECS),
+            cache_size: Some(MOLECULE_DEFAULT_CACHE_SIZE),
+            storage_engine: None,
+            in_memory: false,
+            allow_unauthenticated: false,
+            repair: false,
             cli: false,
             enable_logging: false,
         }
     }
 }
+
+impl Args {
+    /// Fills in the options not given on the command line from a config file.
+    pub fn with_config(self, config: Config) -> Self {
+        Self {
+            addr: self.addr.or(config.addr),
+            port: self.port.or(config.port),
+            data_dir: self.data_dir.or(config.data_dir),
+            max_frame_size: self.max_frame_size.or(config.max_frame_size),
+            ttl_sweep_interval: self.ttl_sweep_interval.or(config.ttl_sweep_interval),
+            compaction_interval: self.compaction_interval.or(config.compaction_interval),
+            cache_size: self.cache_size.or(config.cache_size),
+            storage_engine: self.storage_engine.or(config.storage_engine),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_line_takes_precedence_over_config() {
+        let config = Config {
+            port: Some(7000),
+            data_dir: Some("/var/lib/molecule".into()),
+            storage_engine: Some(StorageEngine::Json),
+            ..Default::default()
+        };
+        let args = Args::parse_from(["molecule", "--port", "9000", "--cache-size", "1024"])
+            .with_config(config);
+
+        assert_eq!(args.port, Some(9000));
+        assert_eq!(args.cache_size, Some(1024));
+        assert_eq!(args.data_dir.as_deref(), Some("/var/lib/molecule"));
+        assert_eq!(args.storage_engine, Some(StorageEngine::Json));
+        assert_eq!(args.addr, None);
+    }
+}
@@ -1,50 +1,763 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
 use tokio::fs::{self};
+use tokio::task;
 
-use crate::{constants::MOLECULE_AUTH_FILE_PATH, molecule::Molecule, proto::AuthInfo};
+use crate::{
+    constants::MOLECULE_AUTH_FILE_PATH,
+    core::{atomic::write_atomic, error::CoreError},
+    molecule::Molecule,
+    proto::{DatabaseInputType, InputSource},
+};
+
+/// What a role allows its users to do. Every database command requires one of these, see
+/// [`DatabaseInputType::required_permission`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// List collections, read records and list indexes.
+    Read,
+    /// Create, update and delete records.
+    Write,
+    /// Create and delete collections.
+    ManageCollections,
+    /// Create and drop indexes.
+    ManageIndexes,
+    /// Compact collections and stop the database.
+    Maintenance,
+    /// Create, drop and list users and roles, and change the passwords of other users.
+    ManageUsers,
+}
+
+/// Roles every database has, which can't be dropped or redefined.
+const BUILTIN_ROLES: &[(&str, &[Permission])] = &[
+    ("read", &[Permission::Read]),
+    (
+        "readWrite",
+        &[
+            Permission::Read,
+            Permission::Write,
+            Permission::ManageCollections,
+            Permission::ManageIndexes,
+        ],
+    ),
+    ("admin", Permission::ALL),
+];
+
+/// Role of users set up with `--auth` and of users from single user auth stores.
+const MOLECULE_ADMIN_ROLE: &str = "admin";
+
+/// bcrypt cost of password hashes. Tests use the lowest cost so they don't spend seconds hashing.
+const PASSWORD_HASH_COST: u32 = if cfg!(test) { 4 } else { 12 };
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub username: String,
+    /// bcrypt hash of the user's password.
+    pub password: String,
+    #[serde(default = "User::default_role")]
+    pub role: String,
+}
+
+/// A user as listed to clients, without the password hash.
+#[derive(Debug, Clone, Serialize)]
+pub struct UserSummary {
+    pub username: String,
+    pub role: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Role {
+    pub name: String,
+    pub permissions: Vec<Permission>,
+}
+
+/// Every user and custom role, persisted as the auth store.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserStore {
+    pub users: Vec<User>,
+    /// Custom roles, the built-in ones aren't stored.
+    #[serde(default)]
+    pub roles: Vec<Role>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredAuth {
+    Users(UserStore),
+    /// Auth stores from before multiple users held a single user.
+    SingleUser(User),
+}
+
+impl Permission {
+    pub const ALL: &[Self] = &[
+        Self::Read,
+        Self::Write,
+        Self::ManageCollections,
+        Self::ManageIndexes,
+        Self::Maintenance,
+        Self::ManageUsers,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::ManageCollections => "manage_collections",
+            Self::ManageIndexes => "manage_indexes",
+            Self::Maintenance => "maintenance",
+            Self::ManageUsers => "manage_users",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match Self::ALL
+            .iter()
+            .find(|permission| permission.as_str() == value)
+        {
+            Some(permission) => Ok(*permission),
+            None => bail!("Unknown permission: {}", value),
+        }
+    }
+}
+
+impl User {
+    fn default_role() -> String {
+        MOLECULE_ADMIN_ROLE.to_string()
+    }
+}
+
+impl UserStore {
+    pub fn user(&self, username: &str) -> Option<&User> {
+        self.users.iter().find(|user| user.username == username)
+    }
+
+    /// Permissions granted by a built-in or custom role.
+    pub fn role_permissions(&self, role: &str) -> Option<Vec<Permission>> {
+        if let Some((_, permissions)) = BUILTIN_ROLES.iter().find(|(name, _)| *name == role) {
+            return Some(permissions.to_vec());
+        }
+
+        self.roles
+            .iter()
+            .find(|custom_role| custom_role.name == role)
+            .map(|custom_role| custom_role.permissions.clone())
+    }
+
+    fn can_manage_users(&self, user: &User) -> bool {
+        self.role_permissions(&user.role)
+            .is_some_and(|permissions| permissions.contains(&Permission::ManageUsers))
+    }
+}
 
 pub trait MoleculeAuthApi {
+    async fn load_users(&self) -> Result<bool>;
     async fn setup_user(&self, username: String, password: String) -> Result<()>;
     async fn is_valid_user(&self, username: &str, password: &str) -> Result<bool>;
+    async fn auth_required(&self) -> bool;
+    async fn authorize(
+        &self,
+        username: Option<&str>,
+        source: InputSource,
+        input: &DatabaseInputType,
+    ) -> Result<()>;
+    async fn create_user(&self, username: String, password: String, role: String) -> Result<()>;
+    async fn drop_user(&self, username: String) -> Result<()>;
+    async fn change_password(&self, username: String, password: String) -> Result<()>;
+    async fn list_users(&self) -> Vec<UserSummary>;
+    async fn create_role(&self, name: String, permissions: Vec<Permission>) -> Result<()>;
+    async fn drop_role(&self, name: String) -> Result<()>;
+    async fn list_roles(&self) -> Vec<Role>;
+}
+
+trait MoleculeAuthExt {
+    fn auth_path(&self) -> Option<String>;
+    async fn update_users(&self, change: impl FnOnce(&mut UserStore) -> Result<()>) -> Result<()>;
+}
+
+impl MoleculeAuthExt for Molecule {
+    /// Path of the auth store, none when the database runs in memory.
+    fn auth_path(&self) -> Option<String> {
+        self.storage
+            .data_dir()
+            .map(|data_dir| data_dir.path(MOLECULE_AUTH_FILE_PATH))
+    }
+
+    /// Applies a change to a copy of the users, which replaces them once it's persisted to the
+    /// auth store. Databases running in memory keep the users in memory only.
+    async fn update_users(&self, change: impl FnOnce(&mut UserStore) -> Result<()>) -> Result<()> {
+        let mut users = self.users.write().await;
+        let mut updated = users.clone();
+
+        change(&mut updated)?;
+
+        if let Some(auth_path) = self.auth_path() {
+            write_atomic(&auth_path, serde_json::to_vec(&updated)?).await?;
+        }
+
+        *users = updated;
+        Ok(())
+    }
 }
 
 impl MoleculeAuthApi for Molecule {
+    /// Loads the users from the auth store, returning whether there was one.
+    async fn load_users(&self) -> Result<bool> {
+        let Some(auth_path) = self.auth_path() else {
+            return Ok(false);
+        };
+
+        if !fs::try_exists(&auth_path).await? {
+            return Ok(false);
+        }
+
+        let store = match serde_json::from_slice(&fs::read(&auth_path).await?)? {
+            StoredAuth::Users(store) => store,
+            StoredAuth::SingleUser(user) => UserStore {
+                users: vec![user],
+                roles: Vec::new(),
+            },
+        };
+
+        log::info!("Found {} existing user(s).", store.users.len());
+        *self.users.write().await = store;
+
+        Ok(true)
+    }
+
+    /// Loads the users from the auth store, adding an admin with the given credentials unless a
+    /// user with that name already exists. An existing user's password must match the one
+    /// given, so a typo can't go unnoticed until clients fail to authenticate.
     async fn setup_user(&self, username: String, password: String) -> Result<()> {
-        if fs::try_exists(MOLECULE_AUTH_FILE_PATH).await? {
-            let existing_auth_info_bytes = fs::read(MOLECULE_AUTH_FILE_PATH).await?;
-            let existing_auth_info: AuthInfo = serde_json::from_slice(&existing_auth_info_bytes)?;
+        self.load_users().await?;
 
-            log::info!("Found existing user: {}", existing_auth_info.username);
-            *self.active_user.write().await = Some(existing_auth_info);
+        if self.users.read().await.user(&username).is_some() {
+            if !self.is_valid_user(&username, &password).await? {
+                bail!(
+                    "The password given with --auth doesn't match the stored password of user {}.",
+                    username
+                );
+            }
 
+            log::info!("Found existing user: {}", username);
             return Ok(());
         }
 
         log::info!("Setting up user with username: {}", username);
-        let hashed_password = bcrypt::hash(password, 12)?;
-        let auth_info = AuthInfo {
-            username,
-            password: hashed_password,
+        self.create_user(username, password, MOLECULE_ADMIN_ROLE.to_string())
+            .await?;
+        log::info!("Auth store updated for the current session.");
+
+        Ok(())
+    }
+
+    async fn is_valid_user(&self, username: &str, password: &str) -> Result<bool> {
+        let Some(hash) = self
+            .users
+            .read()
+            .await
+            .user(username)
+            .map(|user| user.password.clone())
+        else {
+            return Ok(false);
         };
-        let auth_info_bytes = serde_json::to_vec(&auth_info)?;
 
-        fs::write(MOLECULE_AUTH_FILE_PATH, auth_info_bytes).await?;
-        log::info!("Auth store created for the current session.");
+        verify_password(password.to_string(), hash).await
+    }
 
-        *self.active_user.write().await = Some(auth_info);
+    /// Whether clients must authenticate during the handshake, which is the case once a user
+    /// is set up.
+    async fn auth_required(&self) -> bool {
+        !self.users.read().await.users.is_empty()
+    }
+
+    /// Checks that the role of the user a session authenticated as allows a command. Sessions
+    /// may run any command while no user is set up, except for managing users, which only the
+    /// CLI may do until the first user exists. Users may always change their own password.
+    async fn authorize(
+        &self,
+        username: Option<&str>,
+        source: InputSource,
+        input: &DatabaseInputType,
+    ) -> Result<()> {
+        let Some(permission) = input.required_permission() else {
+            return Ok(());
+        };
+
+        let users = self.users.read().await;
+
+        if users.users.is_empty() {
+            if permission == Permission::ManageUsers && source != InputSource::Cli {
+                return Err(CoreError::PermissionDenied(
+                    "Users can only be managed from the CLI until the first user exists."
+                        .to_string(),
+                )
+                .into());
+            }
+
+            return Ok(());
+        }
+
+        let Some(username) = username else {
+            return Err(CoreError::PermissionDenied(
+                "The session is not authenticated.".to_string(),
+            )
+            .into());
+        };
+
+        if let DatabaseInputType::ChangePassword(target, _) = input
+            && target == username
+        {
+            return Ok(());
+        }
+
+        let allowed = users
+            .user(username)
+            .and_then(|user| users.role_permissions(&user.role))
+            .is_some_and(|permissions| permissions.contains(&permission));
+
+        if !allowed {
+            return Err(CoreError::PermissionDenied(format!(
+                "User {} is missing the {} permission.",
+                username,
+                permission.as_str()
+            ))
+            .into());
+        }
 
         Ok(())
     }
 
-    async fn is_valid_user(&self, username: &str, password: &str) -> Result<bool> {
-        if let Some(auth_info) = &*self.active_user.read().await {
-            if auth_info.username != username {
-                return Ok(false);
+    async fn create_user(&self, username: String, password: String, role: String) -> Result<()> {
+        if username.is_empty() || username.contains(':') || username.contains(char::is_whitespace) {
+            return Err(CoreError::ValidationFailed(
+                "Usernames must be non-empty, without whitespace or `:`.".to_string(),
+            )
+            .into());
+        }
+
+        if password.is_empty() {
+            return Err(
+                CoreError::ValidationFailed("Passwords must be non-empty.".to_string()).into(),
+            );
+        }
+
+        let hashed_password = hash_password(password).await?;
+
+        self.update_users(|store| {
+            if store.role_permissions(&role).is_none() {
+                return Err(CoreError::RoleNotFound(role).into());
+            }
+
+            if store.user(&username).is_some() {
+                return Err(CoreError::ValidationFailed(format!(
+                    "User {} already exists.",
+                    username
+                ))
+                .into());
             }
 
-            return Ok(bcrypt::verify(password, &auth_info.password)?);
+            store.users.push(User {
+                username,
+                password: hashed_password,
+                role,
+            });
+            Ok(())
+        })
+        .await
+    }
+
+    /// Drops a user, unless it's the last one allowed to manage users, which would leave nobody
+    /// able to set up users again.
+    async fn drop_user(&self, username: String) -> Result<()> {
+        self.update_users(|store| {
+            let Some(position) = store
+                .users
+                .iter()
+                .position(|user| user.username == username)
+            else {
+                return Err(CoreError::UserNotFound(username).into());
+            };
+
+            let dropped = store.users.remove(position);
+
+            if store.can_manage_users(&dropped)
+                && !store.users.iter().any(|user| store.can_manage_users(user))
+            {
+                return Err(CoreError::ValidationFailed(format!(
+                    "User {} is the last one allowed to manage users.",
+                    username
+                ))
+                .into());
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn change_password(&self, username: String, password: String) -> Result<()> {
+        if password.is_empty() {
+            return Err(
+                CoreError::ValidationFailed("Passwords must be non-empty.".to_string()).into(),
+            );
+        }
+
+        let hashed_password = hash_password(password).await?;
+
+        self.update_users(|store| {
+            let Some(user) = store
+                .users
+                .iter_mut()
+                .find(|user| user.username == username)
+            else {
+                return Err(CoreError::UserNotFound(username).into());
+            };
+
+            user.password = hashed_password;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_users(&self) -> Vec<UserSummary> {
+        self.users
+            .read()
+            .await
+            .users
+            .iter()
+            .map(|user| UserSummary {
+                username: user.username.clone(),
+                role: user.role.clone(),
+            })
+            .collect()
+    }
+
+    async fn create_role(&self, name: String, permissions: Vec<Permission>) -> Result<()> {
+        if name.is_empty() {
+            return Err(
+                CoreError::ValidationFailed("Role names must be non-empty.".to_string()).into(),
+            );
         }
 
-        Ok(false)
+        self.update_users(|store| {
+            if store.role_permissions(&name).is_some() {
+                return Err(
+                    CoreError::ValidationFailed(format!("Role {} already exists.", name)).into(),
+                );
+            }
+
+            store.roles.push(Role { name, permissions });
+            Ok(())
+        })
+        .await
+    }
+
+    /// Drops a custom role, unless a user still holds it.
+    async fn drop_role(&self, name: String) -> Result<()> {
+        self.update_users(|store| {
+            if BUILTIN_ROLES.iter().any(|(builtin, _)| *builtin == name) {
+                return Err(CoreError::ValidationFailed(format!(
+                    "Built-in role {} can't be dropped.",
+                    name
+                ))
+                .into());
+            }
+
+            if let Some(user) = store.users.iter().find(|user| user.role == name) {
+                return Err(CoreError::ValidationFailed(format!(
+                    "Role {} is still held by user {}.",
+                    name, user.username
+                ))
+                .into());
+            }
+
+            let roles_before = store.roles.len();
+            store.roles.retain(|role| role.name != name);
+
+            if store.roles.len() == roles_before {
+                return Err(CoreError::RoleNotFound(name).into());
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Every built-in and custom role.
+    async fn list_roles(&self) -> Vec<Role> {
+        let custom_roles = self.users.read().await.roles.clone();
+
+        BUILTIN_ROLES
+            .iter()
+            .map(|(name, permissions)| Role {
+                name: name.to_string(),
+                permissions: permissions.to_vec(),
+            })
+            .chain(custom_roles)
+            .collect()
+    }
+}
+
+/// Hashes a password on the blocking thread pool, as bcrypt is slow enough on purpose to stall
+/// every other task on the executor thread.
+async fn hash_password(password: String) -> Result<String> {
+    Ok(task::spawn_blocking(move || bcrypt::hash(password, PASSWORD_HASH_COST)).await??)
+}
+
+/// Checks a password against its hash on the blocking thread pool, see [`hash_password`].
+async fn verify_password(password: String, hash: String) -> Result<bool> {
+    Ok(task::spawn_blocking(move || bcrypt::verify(password, &hash)).await??)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn first_user_is_created_from_cli_only() {
+        let molecule = Molecule::in_memory();
+        let create_user = DatabaseInputType::CreateUser(
+            "alice".into(),
+            "secret".into(),
+            MOLECULE_ADMIN_ROLE.into(),
+        );
+        let err = molecule
+            .authorize(None, InputSource::Tcp, &create_user)
+            .await
+            .unwrap_err();
+
+        assert_eq!(CoreError::from(&err).code(), "permission_denied");
+        assert!(
+            molecule
+                .authorize(None, InputSource::Tcp, &DatabaseInputType::CollectionsList)
+                .await
+                .is_ok()
+        );
+        assert!(
+            molecule
+                .authorize(None, InputSource::Cli, &create_user)
+                .await
+                .is_ok()
+        );
+    }
+
+    async fn user(molecule: &Molecule, username: &str, password: &str, role: &str) {
+        molecule
+            .create_user(username.into(), password.into(), role.into())
+            .await
+            .unwrap();
+    }
+
+    async fn denied(molecule: &Molecule, username: &str, input: DatabaseInputType) -> String {
+        let err = molecule
+            .authorize(Some(username), InputSource::Tcp, &input)
+            .await
+            .unwrap_err();
+
+        CoreError::from(&err).code().to_string()
+    }
+
+    #[tokio::test]
+    async fn read_users_are_denied_writes() {
+        let molecule = Molecule::in_memory();
+        user(&molecule, "reader", "secret", "read").await;
+
+        assert!(
+            molecule
+                .authorize(
+                    Some("reader"),
+                    InputSource::Tcp,
+                    &DatabaseInputType::CollectionsList
+                )
+                .await
+                .is_ok()
+        );
+        assert_eq!(
+            denied(
+                &molecule,
+                "reader",
+                DatabaseInputType::CreateRecord("users".into(), Default::default()),
+            )
+            .await,
+            "permission_denied"
+        );
+        assert_eq!(
+            denied(
+                &molecule,
+                "reader",
+                DatabaseInputType::CreateCollection("users".into())
+            )
+            .await,
+            "permission_denied"
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_roles_grant_their_permissions() {
+        let molecule = Molecule::in_memory();
+        user(&molecule, "admin", "secret", MOLECULE_ADMIN_ROLE).await;
+        molecule
+            .create_role("indexer".into(), vec![Permission::ManageIndexes])
+            .await
+            .unwrap();
+        user(&molecule, "bob", "secret", "indexer").await;
+
+        assert!(
+            molecule
+                .authorize(
+                    Some("bob"),
+                    InputSource::Tcp,
+                    &DatabaseInputType::DropIndex("users".into(), "name_1".into()),
+                )
+                .await
+                .is_ok()
+        );
+        assert_eq!(
+            denied(
+                &molecule,
+                "bob",
+                DatabaseInputType::ListIndexes("users".into())
+            )
+            .await,
+            "permission_denied"
+        );
+        assert!(
+            molecule
+                .list_roles()
+                .await
+                .iter()
+                .any(|role| role.name == "indexer")
+        );
+
+        let err = molecule
+            .create_role("read".into(), vec![Permission::Write])
+            .await
+            .unwrap_err();
+
+        assert_eq!(CoreError::from(&err).code(), "validation_failed");
+    }
+
+    #[tokio::test]
+    async fn users_can_change_their_own_password() {
+        let molecule = Molecule::in_memory();
+        user(&molecule, "admin", "secret", MOLECULE_ADMIN_ROLE).await;
+        user(&molecule, "reader", "old", "read").await;
+
+        assert!(
+            molecule
+                .authorize(
+                    Some("reader"),
+                    InputSource::Tcp,
+                    &DatabaseInputType::ChangePassword("reader".into(), "new".into()),
+                )
+                .await
+                .is_ok()
+        );
+        assert_eq!(
+            denied(
+                &molecule,
+                "reader",
+                DatabaseInputType::ChangePassword("admin".into(), "new".into()),
+            )
+            .await,
+            "permission_denied"
+        );
+
+        molecule
+            .change_password("reader".into(), "new".into())
+            .await
+            .unwrap();
+
+        assert!(!molecule.is_valid_user("reader", "old").await.unwrap());
+        assert!(molecule.is_valid_user("reader", "new").await.unwrap());
+        assert!(!molecule.is_valid_user("nobody", "new").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn last_user_manager_cant_be_dropped() {
+        let molecule = Molecule::in_memory();
+        user(&molecule, "admin", "secret", MOLECULE_ADMIN_ROLE).await;
+        user(&molecule, "reader", "secret", "read").await;
+
+        let err = molecule.drop_user("admin".into()).await.unwrap_err();
+
+        assert_eq!(CoreError::from(&err).code(), "validation_failed");
+        assert_eq!(molecule.list_users().await.len(), 2);
+
+        molecule.drop_user("reader".into()).await.unwrap();
+        user(&molecule, "other", "secret", MOLECULE_ADMIN_ROLE).await;
+        molecule.drop_user("admin".into()).await.unwrap();
+
+        let usernames: Vec<_> = molecule
+            .list_users()
+            .await
+            .into_iter()
+            .map(|user| user.username)
+            .collect();
+
+        assert_eq!(usernames, ["other"]);
+    }
+
+    #[tokio::test]
+    async fn held_roles_cant_be_dropped() {
+        let molecule = Molecule::in_memory();
+        user(&molecule, "admin", "secret", MOLECULE_ADMIN_ROLE).await;
+        molecule
+            .create_role("auditor".into(), vec![Permission::Read])
+            .await
+            .unwrap();
+        user(&molecule, "carol", "secret", "auditor").await;
+
+        let err = molecule.drop_role("auditor".into()).await.unwrap_err();
+        assert_eq!(CoreError::from(&err).code(), "validation_failed");
+
+        let err = molecule.drop_role("admin".into()).await.unwrap_err();
+        assert_eq!(CoreError::from(&err).code(), "validation_failed");
+
+        molecule.drop_user("carol".into()).await.unwrap();
+        molecule.drop_role("auditor".into()).await.unwrap();
+
+        let err = molecule.drop_role("auditor".into()).await.unwrap_err();
+        assert_eq!(CoreError::from(&err).code(), "role_not_found");
+    }
+
+    #[tokio::test]
+    async fn loads_single_user_auth_stores() {
+        use crate::core::{data_dir::DataDir, storage::StorageEngine};
+
+        let data_dir = DataDir::temp();
+        let molecule = Molecule::on_disk(StorageEngine::Segment, data_dir.clone()).await;
+        let legacy_store = serde_json::json!({
+            "username": "alice",
+            "password": hash_password("secret".into()).await.unwrap(),
+        });
+
+        fs::write(
+            data_dir.path(MOLECULE_AUTH_FILE_PATH),
+            serde_json::to_vec(&legacy_store).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert!(molecule.load_users().await.unwrap());
+        assert!(molecule.auth_required().await);
+        assert!(molecule.is_valid_user("alice", "secret").await.unwrap());
+
+        let users = molecule.list_users().await;
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].role, MOLECULE_ADMIN_ROLE);
+        assert!(
+            molecule
+                .authorize(
+                    Some("alice"),
+                    InputSource::Tcp,
+                    &DatabaseInputType::DropUser("bob".into()),
+                )
+                .await
+                .is_ok()
+        );
+
+        fs::remove_dir_all(data_dir.root()).await.unwrap();
     }
 }
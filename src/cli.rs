@@ -6,13 +6,23 @@ use tokio::io::AsyncBufReadExt;
 use tokio::io::BufReader;
 use tokio::signal;
 
+use crate::auth::MoleculeAuthApi;
+use crate::auth::Permission;
+use crate::compaction::MoleculeCompactionApi;
 use crate::core::collection::MoleculeCoreCollectionApi;
+use crate::core::error::CoreError;
+use crate::core::index::MoleculeCoreIndexApi;
 use crate::core::record::MoleculeCoreRecordsApi;
+use crate::core::storage::Storage;
 use crate::molecule::Molecule;
 use crate::proto::DatabaseInputType;
 use crate::proto::InputSource;
 use crate::proto::parse_str_to_db_input_type;
 
+trait MoleculeCliHandle {
+    async fn handle_cli_command(&self, input: DatabaseInputType) -> Result<bool>;
+}
+
 pub trait MoleculeCliApi {
     async fn start_cli(&self) -> Result<()>;
 }
@@ -35,63 +45,215 @@ impl MoleculeCliApi for Molecule {
                     let parsed_input = match parse_str_to_db_input_type(trimmed.to_string(), InputSource::Cli) {
                         Ok(pinput) => pinput,
                         Err(err) => {
-                            println!("{}", err.to_string());
+                            println!("{}", err);
                             continue;
                         }
                     };
 
-                    match parsed_input {
-                        DatabaseInputType::Stop => break,
-                        DatabaseInputType::CollectionsList => {
-                            let collections = self.list_collections().await?;
-
-                            if collections.is_empty() {
-                                println!("No collections to list.");
-                            }
-
-                            for collection in collections {
-                                println!("{}({})", collection.name, collection.collection_id);
-                            }
-                        },
-                        DatabaseInputType::Collection(collection_id) => {
-                            if let Some(collection) = self.get_collection_name(collection_id).await? {
-                                println!("{}", collection);
-                            } else {
-                                println!("No collection found with that ID.");
-                            }
-                        },
-                        DatabaseInputType::CollectionRecords(collection_id) => {
-                            let records = self.get_records(collection_id).await?;
-
-                            if records.is_empty() {
-                                println!("No records in collection.");
-                            }
-
-                            for record in records {
-                                println!("{}", serde_json::to_string_pretty(&record)?);
-                            }
-                        },
-                        DatabaseInputType::CreateCollection(name) =>  { self.create_collection(name).await?; },
-                        DatabaseInputType::CreateRecord(collection_id, contents) => {
-                            self.create_record(collection_id, contents).await?;
-                        },
-                        DatabaseInputType::DeleteCollection(collection_id) =>  { self.delete_collection(collection_id).await?; },
-                        DatabaseInputType::DeleteRecord(collection_id, record_id) => { self.delete_record(collection_id, record_id).await?; },
-                        DatabaseInputType::IdRecord(collection_id, record_id) => {
-                            if let Some(record) = self.get_record_by_id(collection_id, record_id).await? {
-                                println!("{}", serde_json::to_string_pretty(&record)?);
-                            } else {
-                                println!("No record found in collection with the specified ID.");
+                    match self.handle_cli_command(parsed_input).await {
+                        Ok(true) => {}
+                        Ok(false) => break,
+                        Err(err) => {
+                            let core_err = CoreError::from(&err);
+                            if matches!(core_err, CoreError::Storage(_) | CoreError::Internal(_)) {
+                                log::error!("Database error: {:#}", err);
                             }
+                            println!("{}", core_err);
                         }
-                        DatabaseInputType::Noop => log::info!("Received empty (noop) operation."),
-                    };
+                    }
                 },
                 _ = signal::ctrl_c() => break,
             }
         }
 
         log::info!("Gracefully shutting down...");
+        self.storage.checkpoint().await?;
         process::exit(0);
     }
 }
+
+impl MoleculeCliHandle for Molecule {
+    /// Runs a single command, returning whether the CLI should keep running.
+    async fn handle_cli_command(&self, input: DatabaseInputType) -> Result<bool> {
+        match input {
+            DatabaseInputType::Stop => return Ok(false),
+            DatabaseInputType::CollectionsList => {
+                let collections = self.list_collections().await?;
+
+                if collections.is_empty() {
+                    println!("No collections to list.");
+                }
+
+                for collection in collections {
+                    println!("{}({})", collection.name, collection.collection_id);
+                }
+            }
+            DatabaseInputType::Collection(collection_id) => {
+                if let Some(collection) = self.get_collection_name(collection_id).await? {
+                    println!("{}", collection);
+                } else {
+                    println!("No collection found with that ID.");
+                }
+            }
+            DatabaseInputType::CollectionRecords(collection_id, options) => {
+                let records = self.get_records(collection_id, options).await?;
+
+                if records.is_empty() {
+                    println!("No records in collection.");
+                }
+
+                for record in records {
+                    println!("{}", serde_json::to_string_pretty(&record)?);
+                }
+            }
+            DatabaseInputType::FindRecords(collection_id, filter, options) => {
+                let records = self.find_records(collection_id, filter, options).await?;
+
+                if records.is_empty() {
+                    println!("No records matched the filter.");
+                }
+
+                for record in records {
+                    println!("{}", serde_json::to_string_pretty(&record)?);
+                }
+            }
+            DatabaseInputType::CreateCollection(name) => {
+                self.create_collection(name).await?;
+            }
+            DatabaseInputType::CreateRecord(collection_id, contents) => {
+                self.create_record(collection_id, contents).await?;
+            }
+            DatabaseInputType::CreateRecords(collection_id, contents, options) => {
+                let outcome = self
+                    .create_records(collection_id, contents, options)
+                    .await?;
+
+                println!("Created {} record(s).", outcome.inserted_ids.len());
+
+                for failure in outcome.errors {
+                    println!("Record #{} failed: {}", failure.index, failure.message);
+                }
+            }
+            DatabaseInputType::UpdateRecord(collection_id, record_id, update) => {
+                if let Some(record) = self.update_record(collection_id, record_id, update).await? {
+                    println!("{}", serde_json::to_string_pretty(&record)?);
+                } else {
+                    println!("No record found in collection with the specified ID.");
+                }
+            }
+            DatabaseInputType::ReplaceRecord(collection_id, record_id, contents) => {
+                if let Some(record) = self
+                    .replace_record(collection_id, record_id, contents)
+                    .await?
+                {
+                    println!("{}", serde_json::to_string_pretty(&record)?);
+                } else {
+                    println!("No record found in collection with the specified ID.");
+                }
+            }
+            DatabaseInputType::UpdateRecords(collection_id, filter, update, options) => {
+                let outcome = self
+                    .update_records(collection_id, filter, update, options)
+                    .await?;
+
+                println!(
+                    "Matched {} record(s), modified {}.",
+                    outcome.matched, outcome.modified
+                );
+
+                if let Some(record_id) = outcome.upserted_id {
+                    println!("Upserted record with ID: {}", record_id);
+                }
+            }
+            DatabaseInputType::DeleteRecords(collection_id, filter) => {
+                let outcome = self.delete_records(collection_id, filter).await?;
+                println!("Deleted {} record(s).", outcome.deleted);
+            }
+            DatabaseInputType::CreateIndex(collection_id, fields, options) => {
+                let name = self.create_index(collection_id, fields, options).await?;
+                println!("Index {} is ready.", name);
+            }
+            DatabaseInputType::DropIndex(collection_id, name) => {
+                if !self.drop_index(collection_id, name).await? {
+                    println!("No index found in collection with that name.");
+                }
+            }
+            DatabaseInputType::ListIndexes(collection_id) => {
+                for index in self.list_indexes(collection_id).await? {
+                    println!("{} ({})", index.name, index.fields.join(", "));
+                }
+            }
+            DatabaseInputType::DeleteCollection(collection_id) => {
+                if self.delete_collection(collection_id).await?.is_none() {
+                    println!("No collection found with that ID.");
+                }
+            }
+            DatabaseInputType::DeleteRecord(collection_id, record_id) => {
+                if self
+                    .delete_record(collection_id, record_id)
+                    .await?
+                    .is_none()
+                {
+                    println!("No record found in collection with the specified ID.");
+                }
+            }
+            DatabaseInputType::IdRecord(collection_id, record_id) => {
+                if let Some(record) = self.get_record_by_id(collection_id, record_id).await? {
+                    println!("{}", serde_json::to_string_pretty(&record)?);
+                } else {
+                    println!("No record found in collection with the specified ID.");
+                }
+            }
+            DatabaseInputType::Compact(collection_id) => {
+                match self.compact_collection(collection_id).await? {
+                    Some(reclaimed) => println!("Reclaimed {} bytes.", reclaimed),
+                    None => println!("No collection found with that ID."),
+                }
+            }
+            DatabaseInputType::CreateUser(username, password, role) => {
+                self.create_user(username.clone(), password, role).await?;
+                println!("User {} created.", username);
+            }
+            DatabaseInputType::DropUser(username) => {
+                self.drop_user(username.clone()).await?;
+                println!("User {} dropped.", username);
+            }
+            DatabaseInputType::ChangePassword(username, password) => {
+                self.change_password(username.clone(), password).await?;
+                println!("Password of user {} changed.", username);
+            }
+            DatabaseInputType::ListUsers => {
+                let users = self.list_users().await;
+
+                if users.is_empty() {
+                    println!("No users to list.");
+                }
+
+                for user in users {
+                    println!("{} ({})", user.username, user.role);
+                }
+            }
+            DatabaseInputType::CreateRole(name, permissions) => {
+                self.create_role(name.clone(), permissions).await?;
+                println!("Role {} created.", name);
+            }
+            DatabaseInputType::DropRole(name) => {
+                self.drop_role(name.clone()).await?;
+                println!("Role {} dropped.", name);
+            }
+            DatabaseInputType::ListRoles => {
+                for role in self.list_roles().await {
+                    let permissions: Vec<&str> =
+                        role.permissions.iter().map(Permission::as_str).collect();
+                    println!("{} ({})", role.name, permissions.join(", "));
+                }
+            }
+            DatabaseInputType::Noop => log::info!("Received empty (noop) operation."),
+            DatabaseInputType::Quit => {
+                log::info!("QUIT only ends TCP sessions, use STOP to shut down.")
+            }
+        };
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::core::storage::Storage;
+use crate::molecule::Molecule;
+
+pub trait MoleculeCompactionApi {
+    async fn start_compaction_monitor(self: Arc<Self>);
+    async fn compact_collection(&self, collection_id: String) -> Result<Option<u64>>;
+    async fn compact_collections(&self) -> Result<u64>;
+}
+
+impl MoleculeCompactionApi for Molecule {
+    /// Compacts the collections with enough space to reclaim every `compaction_interval` for as
+    /// long as the database runs. A failed compaction is logged and retried on the next tick.
+    async fn start_compaction_monitor(self: Arc<Self>) {
+        let mut interval = time::interval(self.compaction_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.compact_collections().await {
+                log::error!("Compaction failed: {}", e);
+            }
+        }
+    }
+
+    /// Compacts a collection, returning how many bytes were reclaimed, or `None` if there's no
+    /// collection with that ID. Writes to the collection wait for the compaction to finish,
+    /// queries don't.
+    async fn compact_collection(&self, collection_id: String) -> Result<Option<u64>> {
+        let map_guard = self.collection_map_lock.read().await;
+        let _guard = self
+            .collection_write_lock(&collection_id)
+            .lock_owned()
+            .await;
+
+        // Checked with the write lock held, so the collection can't be deleted until the
+        // compaction is done.
+        if !self
+            .storage
+            .read_collection_map()
+            .await?
+            .iter()
+            .any(|c| c.collection_id == collection_id)
+        {
+            return Ok(None);
+        }
+
+        drop(map_guard);
+
+        let reclaimed = self.storage.compact(&collection_id).await?;
+
+        log::info!(
+            "Compacted collection {}, reclaiming {} bytes.",
+            collection_id,
+            reclaimed
+        );
+
+        Ok(Some(reclaimed))
+    }
+
+    /// Compacts every collection worth compacting, returning how many bytes were reclaimed in
+    /// total. A collection that fails to compact is logged and skipped.
+    async fn compact_collections(&self) -> Result<u64> {
+        let mut reclaimed = 0;
+
+        for collection_id in self.storage.compaction_candidates().await {
+            match self.compact_collection(collection_id.clone()).await {
+                Ok(collection_reclaimed) => reclaimed += collection_reclaimed.unwrap_or(0),
+                Err(e) => log::error!("Compacting collection {} failed: {}", collection_id, e),
+            }
+        }
+
+        Ok(reclaimed)
+    }
+}
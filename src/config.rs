@@ -0,0 +1,68 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use tokio::fs;
+
+use crate::core::storage::StorageEngine;
+
+/// Options read from the JSON file given with `--config`, named like their command line
+/// arguments in snake case, as in `{"data_dir": "/var/lib/molecule", "port": 7878}`. Arguments
+/// given on the command line take precedence over the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub addr: Option<String>,
+    pub port: Option<u32>,
+    pub data_dir: Option<String>,
+    pub max_frame_size: Option<usize>,
+    pub ttl_sweep_interval: Option<u64>,
+    pub compaction_interval: Option<u64>,
+    pub cache_size: Option<usize>,
+    pub storage_engine: Option<StorageEngine>,
+}
+
+impl Config {
+    pub async fn load(path: &str) -> Result<Self> {
+        let contents = fs::read(path)
+            .await
+            .map_err(|e| anyhow!("Could not read config file {}: {}", path, e))?;
+
+        serde_json::from_slice(&contents)
+            .map_err(|e| anyhow!("Could not parse config file {}: {}", path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn load(contents: &str) -> Result<Config> {
+        let path =
+            std::env::temp_dir().join(format!("molecule-config-{}.json", uuid::Uuid::new_v4()));
+        let path = path.to_string_lossy();
+
+        fs::write(path.as_ref(), contents).await.unwrap();
+        let config = Config::load(&path).await;
+        fs::remove_file(path.as_ref()).await.unwrap();
+
+        config
+    }
+
+    #[tokio::test]
+    async fn loads_config_files() {
+        let config = load(r#"{"port": 7878, "storage_engine": "json"}"#)
+            .await
+            .unwrap();
+
+        assert_eq!(config.port, Some(7878));
+        assert_eq!(config.storage_engine, Some(StorageEngine::Json));
+        assert_eq!(config.data_dir, None);
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_keys() {
+        let err = load(r#"{"port": 7878, "prot": 7979}"#).await.unwrap_err();
+
+        assert!(err.to_string().contains("prot"));
+        assert!(Config::load("/nonexistent/molecule.json").await.is_err());
+    }
+}
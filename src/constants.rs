@@ -1,7 +1,26 @@
-pub const MOLECULE_DOT_FILE_PATH: &str = ".molecule";
-pub const MOLECULE_AUTH_FILE_PATH: &str = ".molecule/auth.store";
-pub const MOLECULE_DEFAULT_DATA_PATH: &str = ".molecule/data";
-pub const MOLECULE_DEFAULT_DATA_COLLECTIONS_PATH: &str = ".molecule/data/collections";
-pub const MOLECULE_DEFAULT_DATA_COLLECTION_META_PATH: &str = ".molecule/data/map.json";
+pub const MOLECULE_DEFAULT_DATA_DIR: &str = ".molecule";
+// Paths of everything stored in the data directory, relative to it.
+pub const MOLECULE_AUTH_FILE_PATH: &str = "auth.store";
+pub const MOLECULE_LOCK_FILE_PATH: &str = "molecule.lock";
+pub const MOLECULE_WAL_PATH: &str = "wal.log";
+pub const MOLECULE_DATA_PATH: &str = "data";
+pub const MOLECULE_DATA_COLLECTIONS_PATH: &str = "data/collections";
+pub const MOLECULE_DATA_COLLECTION_META_PATH: &str = "data/map.json";
+pub const MOLECULE_DATA_INDEXES_PATH: &str = "data/indexes";
+pub const MOLECULE_DATA_SEGMENTS_PATH: &str = "data/segments";
+pub const MOLECULE_DATA_ENGINE_PATH: &str = "data/engine";
+pub const MOLECULE_DATA_LOST_FOUND_PATH: &str = "data/lost+found";
+pub const MOLECULE_WAL_CHECKPOINT_SIZE: u64 = 32 * 1024 * 1024;
+pub const MOLECULE_SEGMENT_SIZE: u64 = 8 * 1024 * 1024;
+pub const MOLECULE_MIN_COMPACTION_GARBAGE: u64 = 4 * 1024 * 1024;
 pub const MOLECULE_DEFAULT_ADDR: &str = "0.0.0.0";
 pub const MOLECULE_DEFAULT_PORT: u32 = 80;
+pub const MOLECULE_DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+pub const MOLECULE_MAX_HANDSHAKE_FRAME_SIZE: usize = 4 * 1024;
+pub const MOLECULE_MAX_DISCARDED_FRAME_FACTOR: usize = 4;
+pub const MOLECULE_PROTOCOL_VERSION: u32 = 2;
+pub const MOLECULE_MIN_PROTOCOL_VERSION: u32 = 1;
+pub const MOLECULE_MAX_REQUEST_TAG_LEN: usize = 64;
+pub const MOLECULE_DEFAULT_TTL_SWEEP_INTERVAL_SECS: u64 = 60;
+pub const MOLECULE_DEFAULT_COMPACTION_INTERVAL_SECS: u64 = 300;
+pub const MOLECULE_DEFAULT_CACHE_SIZE: usize = 256 * 1024 * 1024;
@@ -0,0 +1,29 @@
+use std::io;
+use std::path::Path;
+
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+
+/// Suffix of the temporary file a file is written to before replacing it.
+pub const TEMP_FILE_SUFFIX: &str = ".tmp";
+
+/// Replaces the file at `path` with `contents` without ever leaving it half-written: the
+/// contents are written and synced to a temporary file first, which is then renamed over
+/// `path`. The parent directory is synced afterwards so the rename itself survives a crash. A
+/// crash at any point leaves either the old or the new file in place, plus at worst a stray
+/// temporary file.
+pub async fn write_atomic(path: &str, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let temp_path = format!("{}{}", path, TEMP_FILE_SUFFIX);
+    let mut file = File::create(&temp_path).await?;
+
+    file.write_all(contents.as_ref()).await?;
+    file.sync_all().await?;
+    fs::rename(temp_path, path).await?;
+
+    let parent = match Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    File::open(parent).await?.sync_all().await
+}
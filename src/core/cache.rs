@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::core::{index::CollectionIndexes, record::Record};
+
+/// A collection's records together with its indexes, as held in memory.
+#[derive(Debug, Clone)]
+pub struct LoadedCollection {
+    pub records: Vec<Record>,
+    pub indexes: CollectionIndexes,
+    /// Size of the collection in storage, used as an estimate of its memory usage.
+    pub size: usize,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    collection: Arc<LoadedCollection>,
+    last_used: u64,
+}
+
+/// Collections kept in memory so they're only read from disk once. When the collections
+/// held go over the memory budget, the least recently used ones are evicted.
+#[derive(Debug)]
+pub struct CollectionCache {
+    budget: usize,
+    used: usize,
+    clock: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl CollectionCache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            used: 0,
+            clock: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, collection_id: &str) -> Option<Arc<LoadedCollection>> {
+        self.clock += 1;
+
+        let entry = self.entries.get_mut(collection_id)?;
+        entry.last_used = self.clock;
+
+        Some(entry.collection.clone())
+    }
+
+    /// Caches a collection, replacing the version cached before. Collections larger than the
+    /// whole budget are never cached.
+    pub fn insert(&mut self, collection_id: String, collection: Arc<LoadedCollection>) {
+        self.remove(&collection_id);
+
+        let size = collection.size;
+
+        if size > self.budget {
+            return;
+        }
+
+        while self.used + size > self.budget {
+            let Some(lru_id) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(id, _)| id.clone())
+            else {
+                break;
+            };
+
+            log::info!("Evicting collection {} from the cache.", lru_id);
+            self.remove(&lru_id);
+        }
+
+        self.clock += 1;
+        self.used += size;
+        self.entries.insert(
+            collection_id,
+            CacheEntry {
+                collection,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Removes a collection from the cache, returning it if it was cached.
+    pub fn take(&mut self, collection_id: &str) -> Option<Arc<LoadedCollection>> {
+        let entry = self.entries.remove(collection_id)?;
+        self.used -= entry.collection.size;
+
+        Some(entry.collection)
+    }
+
+    pub fn remove(&mut self, collection_id: &str) {
+        self.take(collection_id);
+    }
+}
@@ -1,23 +1,17 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use tokio::fs;
 use uuid::Uuid;
 
-use crate::{
-    constants::{
-        MOLECULE_DEFAULT_DATA_COLLECTION_META_PATH, MOLECULE_DEFAULT_DATA_COLLECTIONS_PATH,
-    },
-    molecule::Molecule,
-};
+use crate::{core::storage::Storage, molecule::Molecule};
 
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub struct Collection {
     pub collection_id: String,
     pub name: String,
 }
 
 pub trait MoleculeCoreCollectionApi {
-    async fn delete_collection(&self, collection_id: String) -> Result<String>;
+    async fn delete_collection(&self, collection_id: String) -> Result<Option<String>>;
     async fn create_collection(&self, name: String) -> Result<String>;
     async fn list_collections(&self) -> Result<Vec<Collection>>;
     async fn get_collection_name(&self, collection_id: String) -> Result<Option<String>>;
@@ -25,23 +19,22 @@ pub trait MoleculeCoreCollectionApi {
 
 impl MoleculeCoreCollectionApi for Molecule {
     async fn list_collections(&self) -> Result<Vec<Collection>> {
-        let collection_meta_content = fs::read(MOLECULE_DEFAULT_DATA_COLLECTION_META_PATH).await?;
-        let parsed_meta: Vec<Collection> = serde_json::from_slice(&collection_meta_content)?;
+        let _guard = self.collection_map_lock.read().await;
 
-        Ok(parsed_meta)
+        self.storage.read_collection_map().await
     }
 
     async fn get_collection_name(&self, collection_id: String) -> Result<Option<String>> {
         let meta_contents = self.list_collections().await?;
         Ok(meta_contents
             .into_iter()
-            .filter(|c| c.collection_id == collection_id)
-            .next()
-            .and_then(|c| Some(c.name)))
+            .find(|c| c.collection_id == collection_id)
+            .map(|c| c.name))
     }
 
     async fn create_collection(&self, name: String) -> Result<String> {
-        let mut meta_contents = self.list_collections().await?;
+        let _guard = self.collection_map_lock.write().await;
+        let mut meta_contents = self.storage.read_collection_map().await?;
         let collection_id = Uuid::new_v4().to_string();
 
         meta_contents.push(Collection {
@@ -49,43 +42,116 @@ impl MoleculeCoreCollectionApi for Molecule {
             name,
         });
 
-        let collection_path = format!(
-            "{}/{}.json",
-            MOLECULE_DEFAULT_DATA_COLLECTIONS_PATH, collection_id
-        );
-
-        fs::write(collection_path, b"[]").await?;
-
-        fs::write(
-            MOLECULE_DEFAULT_DATA_COLLECTION_META_PATH,
-            serde_json::to_vec(&meta_contents)?,
-        )
-        .await?;
+        self.storage
+            .create_collection(&meta_contents, &collection_id)
+            .await?;
         log::info!("Created collection with ID: {}", collection_id);
 
         Ok(collection_id)
     }
 
-    async fn delete_collection(&self, collection_id: String) -> Result<String> {
-        let collection_path = format!(
-            "{}/{}.json",
-            MOLECULE_DEFAULT_DATA_COLLECTIONS_PATH, collection_id
-        );
-        let collections = self.list_collections().await?;
-        let updated_collections = collections
-            .iter()
-            .map(|c| c.collection_id != collection_id)
-            .collect::<Vec<_>>();
+    /// Deletes a collection along with its records and indexes, returning its ID or `None` if
+    /// there's no collection with it.
+    async fn delete_collection(&self, collection_id: String) -> Result<Option<String>> {
+        let _map_guard = self.collection_map_lock.write().await;
+        let _guard = self.lock_collection_for_write(&collection_id).await;
+        let mut collections = self.storage.read_collection_map().await?;
+        let collection_count = collections.len();
 
-        fs::write(
-            MOLECULE_DEFAULT_DATA_COLLECTION_META_PATH,
-            serde_json::to_vec(&updated_collections)?,
-        )
-        .await?;
+        collections.retain(|c| c.collection_id != collection_id);
 
-        fs::remove_file(collection_path).await?;
+        if collections.len() == collection_count {
+            return Ok(None);
+        }
+
+        self.storage
+            .delete_collection(&collections, &collection_id)
+            .await?;
+        self.cache().remove(&collection_id);
+        self.forget_collection_lock(&collection_id);
 
         log::info!("Deleted collection with ID: {}", collection_id);
-        Ok(collection_id)
+        Ok(Some(collection_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::core::error::CoreError;
+    use crate::core::query::QueryOptions;
+    use crate::core::record::MoleculeCoreRecordsApi;
+
+    fn contents(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn deleting_missing_collection_returns_none() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+
+        assert_eq!(
+            molecule.delete_collection("missing".into()).await.unwrap(),
+            None
+        );
+        assert_eq!(molecule.list_collections().await.unwrap().len(), 1);
+
+        molecule
+            .delete_collection(collection_id.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            molecule.delete_collection(collection_id).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn deleting_collection_keeps_others() {
+        let molecule = Molecule::in_memory();
+        let users = molecule.create_collection("users".into()).await.unwrap();
+        let posts = molecule.create_collection("posts".into()).await.unwrap();
+
+        for collection_id in [&users, &posts] {
+            molecule
+                .create_record(collection_id.clone(), contents(json!({"_id": "a"})))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            molecule.delete_collection(users.clone()).await.unwrap(),
+            Some(users.clone())
+        );
+
+        let err = molecule
+            .get_records(users, QueryOptions::default())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            CoreError::from(&err),
+            CoreError::CollectionNotFound(_)
+        ));
+        assert_eq!(
+            molecule.list_collections().await.unwrap(),
+            vec![Collection {
+                collection_id: posts.clone(),
+                name: "posts".into(),
+            }]
+        );
+        assert_eq!(
+            molecule
+                .get_records(posts, QueryOptions::default())
+                .await
+                .unwrap(),
+            vec![contents(json!({"_id": "a"}))]
+        );
     }
 }
@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use anyhow::{Result, bail};
+
+use crate::{
+    core::{collection::MoleculeCoreCollectionApi, storage::Storage},
+    molecule::Molecule,
+};
+
+/// A disagreement between `map.json` and what's stored in the data directory.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// A collection listed in `map.json` has no records stored.
+    MissingCollection(String),
+    /// Stored records belong to no collection listed in `map.json`.
+    OrphanCollection(String),
+    /// Stored indexes belong to no collection listed in `map.json`.
+    OrphanIndex(String),
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingCollection(collection_id) => write!(
+                f,
+                "Collection {} is listed in map.json but has no records stored.",
+                collection_id
+            ),
+            Self::OrphanCollection(collection_id) => write!(
+                f,
+                "Records of collection {} are stored but not listed in map.json.",
+                collection_id
+            ),
+            Self::OrphanIndex(collection_id) => write!(
+                f,
+                "Indexes of collection {} are stored but not listed in map.json.",
+                collection_id
+            ),
+        }
+    }
+}
+
+pub trait MoleculeCoreConsistencyApi {
+    async fn find_inconsistencies(&self) -> Result<Vec<Inconsistency>>;
+    async fn check_consistency(&self, repair: bool) -> Result<()>;
+}
+
+impl MoleculeCoreConsistencyApi for Molecule {
+    async fn find_inconsistencies(&self) -> Result<Vec<Inconsistency>> {
+        let listed: HashSet<String> = self
+            .list_collections()
+            .await?
+            .into_iter()
+            .map(|c| c.collection_id)
+            .collect();
+        let data_files = self.storage.stored_collections().await?;
+        let index_files = self.storage.indexed_collections().await?;
+        let mut inconsistencies = Vec::new();
+
+        for collection_id in &listed {
+            if !data_files.contains(collection_id) {
+                inconsistencies.push(Inconsistency::MissingCollection(collection_id.clone()));
+            }
+        }
+
+        for collection_id in data_files.difference(&listed) {
+            inconsistencies.push(Inconsistency::OrphanCollection(collection_id.clone()));
+        }
+
+        for collection_id in index_files.difference(&listed) {
+            inconsistencies.push(Inconsistency::OrphanIndex(collection_id.clone()));
+        }
+
+        Ok(inconsistencies)
+    }
+
+    /// Makes sure `map.json` and the stored collections agree before the database starts serving.
+    /// Inconsistencies are reported and refuse the startup, unless `repair` is set, in which
+    /// case they're fixed without losing any data:
+    ///
+    /// - Collections missing their records are recreated empty.
+    /// - Orphan records are moved to `lost+found` in the data directory.
+    /// - Orphan indexes are removed, as they only hold data derived from the records.
+    async fn check_consistency(&self, repair: bool) -> Result<()> {
+        let inconsistencies = self.find_inconsistencies().await?;
+
+        if inconsistencies.is_empty() {
+            return Ok(());
+        }
+
+        if !repair {
+            let report = inconsistencies
+                .iter()
+                .map(|i| format!("  - {}", i))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            bail!(
+                "Found {} inconsistencies in the data directory:\n{}\nRestart with --repair to fix them.",
+                inconsistencies.len(),
+                report
+            );
+        }
+
+        for inconsistency in inconsistencies {
+            log::warn!("Repairing: {}", inconsistency);
+            self.storage.repair(&inconsistency).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        constants::{MOLECULE_DATA_COLLECTION_META_PATH, MOLECULE_DATA_LOST_FOUND_PATH},
+        core::{
+            collection::Collection, data_dir::DataDir, record::MoleculeCoreRecordsApi,
+            storage::StorageEngine, wal::Wal,
+        },
+    };
+
+    /// Restarts a database whose `map.json` lost collection `orphan` and gained `missing`,
+    /// which has nothing stored.
+    async fn inconsistent_molecule(
+        engine: StorageEngine,
+        data_dir: &DataDir,
+    ) -> (Molecule, String) {
+        let molecule = Molecule::on_disk(engine, data_dir.clone()).await;
+        let kept = molecule.create_collection("kept".into()).await.unwrap();
+        let orphan = molecule.create_collection("orphan".into()).await.unwrap();
+        let contents: HashMap<_, _> = [("n".to_string(), json!(1))].into();
+        molecule
+            .create_record(orphan.clone(), contents)
+            .await
+            .unwrap();
+        drop(molecule);
+
+        // Checkpoints the log, so replaying it on restart doesn't undo the edit below.
+        Wal::open(data_dir.clone()).await.unwrap();
+
+        let map = vec![
+            Collection {
+                collection_id: kept,
+                name: "kept".into(),
+            },
+            Collection {
+                collection_id: "missing".into(),
+                name: "missing".into(),
+            },
+        ];
+        tokio::fs::write(
+            data_dir.path(MOLECULE_DATA_COLLECTION_META_PATH),
+            serde_json::to_vec(&map).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        (Molecule::on_disk(engine, data_dir.clone()).await, orphan)
+    }
+
+    #[tokio::test]
+    async fn finds_missing_and_orphan_collections() {
+        for engine in [StorageEngine::Json, StorageEngine::Segment] {
+            let data_dir = DataDir::temp();
+            let (molecule, orphan) = inconsistent_molecule(engine, &data_dir).await;
+            let inconsistencies = molecule.find_inconsistencies().await.unwrap();
+
+            assert!(inconsistencies.contains(&Inconsistency::MissingCollection("missing".into())));
+            assert!(inconsistencies.contains(&Inconsistency::OrphanCollection(orphan.clone())));
+            assert!(
+                inconsistencies
+                    .iter()
+                    .all(|i| *i != Inconsistency::OrphanCollection("kept".into()))
+            );
+
+            tokio::fs::remove_dir_all(data_dir.root()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_to_start_without_repair() {
+        for engine in [StorageEngine::Json, StorageEngine::Segment] {
+            let data_dir = DataDir::temp();
+            let (molecule, orphan) = inconsistent_molecule(engine, &data_dir).await;
+            let err = molecule.check_consistency(false).await.unwrap_err();
+
+            assert!(err.to_string().contains("--repair"));
+            assert!(err.to_string().contains(&orphan));
+            // Nothing is touched until the repair is asked for.
+            assert!(!molecule.find_inconsistencies().await.unwrap().is_empty());
+            assert!(
+                !tokio::fs::try_exists(data_dir.path(MOLECULE_DATA_LOST_FOUND_PATH))
+                    .await
+                    .unwrap()
+            );
+
+            tokio::fs::remove_dir_all(data_dir.root()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn repairs_inconsistencies() {
+        for engine in [StorageEngine::Json, StorageEngine::Segment] {
+            let data_dir = DataDir::temp();
+            let (molecule, orphan) = inconsistent_molecule(engine, &data_dir).await;
+
+            molecule.check_consistency(true).await.unwrap();
+
+            assert!(molecule.find_inconsistencies().await.unwrap().is_empty());
+            assert!(
+                molecule
+                    .get_records("missing".into(), Default::default())
+                    .await
+                    .unwrap()
+                    .is_empty()
+            );
+
+            let mut lost_found = tokio::fs::read_dir(data_dir.path(MOLECULE_DATA_LOST_FOUND_PATH))
+                .await
+                .unwrap();
+            let moved = lost_found.next_entry().await.unwrap().unwrap();
+            assert!(moved.file_name().to_string_lossy().starts_with(&orphan));
+
+            tokio::fs::remove_dir_all(data_dir.root()).await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::process;
+
+use anyhow::{Result, bail};
+
+use crate::constants::MOLECULE_LOCK_FILE_PATH;
+
+/// The directory everything the database persists is stored under: the auth store, the
+/// write-ahead log and the collections.
+#[derive(Debug, Clone)]
+pub struct DataDir {
+    root: String,
+}
+
+impl DataDir {
+    pub fn new(root: &str) -> Self {
+        let trimmed = root.trim_end_matches('/');
+
+        Self {
+            root: if trimmed.is_empty() { root } else { trimmed }.to_string(),
+        }
+    }
+
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// Path of `relative` inside the data directory.
+    pub fn path(&self, relative: &str) -> String {
+        format!("{}/{}", self.root, relative)
+    }
+
+    /// Takes an exclusive lock on the data directory, so no other server can open it for as
+    /// long as the returned lock file stays open. The operating system releases the lock when
+    /// the process exits, even if it crashes.
+    pub fn lock(&self) -> Result<File> {
+        let path = self.path(MOLECULE_LOCK_FILE_PATH);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => bail!(
+                "The data directory {} is already in use by another server (see {}).",
+                self.root,
+                path
+            ),
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+
+        file.set_len(0)?;
+        writeln!(file, "{}", process::id())?;
+
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+impl DataDir {
+    /// A fresh data directory under the system's temporary directory, for tests. It's created
+    /// on first use and left for the test to remove.
+    pub fn temp() -> Self {
+        let root = std::env::temp_dir().join(format!("molecule-test-{}", uuid::Uuid::new_v4()));
+
+        Self::new(&root.to_string_lossy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_dir_can_only_be_locked_once() {
+        let data_dir = DataDir::temp();
+        std::fs::create_dir_all(data_dir.root()).unwrap();
+
+        let lock = data_dir.lock().unwrap();
+        let err = data_dir.lock().unwrap_err();
+        assert!(err.to_string().contains("already in use"));
+
+        drop(lock);
+        data_dir.lock().unwrap();
+
+        std::fs::remove_dir_all(data_dir.root()).unwrap();
+    }
+}
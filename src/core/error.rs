@@ -0,0 +1,160 @@
+use std::fmt;
+use std::io;
+
+/// Failures of core operations, each with a stable code clients can branch on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CoreError {
+    /// No collection exists with this ID.
+    CollectionNotFound(String),
+    /// No record of the collection has this ID.
+    RecordNotFound(String),
+    /// No index of the collection has this name.
+    IndexNotFound(String),
+    /// Another record of the collection already holds this key of a unique index.
+    DuplicateKey { index: String, key: String },
+    /// A record's `_id` must be a non-empty string.
+    InvalidId,
+    /// The index can't be created or dropped.
+    InvalidIndex(String),
+    /// The request is well-formed but can't be applied, such as an update incrementing a field
+    /// that isn't a number.
+    ValidationFailed(String),
+    /// Reading or writing the data files failed.
+    Storage(String),
+    /// No user exists with this username.
+    UserNotFound(String),
+    /// No built-in or custom role has this name.
+    RoleNotFound(String),
+    /// The session's role doesn't allow the request.
+    PermissionDenied(String),
+    /// Anything else going wrong while handling the request.
+    Internal(String),
+}
+
+impl CoreError {
+    /// Stable, machine readable name of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::CollectionNotFound(_) => "collection_not_found",
+            Self::RecordNotFound(_) => "record_not_found",
+            Self::IndexNotFound(_) => "index_not_found",
+            Self::DuplicateKey { .. } => "duplicate_key",
+            Self::InvalidId => "invalid_id",
+            Self::InvalidIndex(_) => "invalid_index",
+            Self::ValidationFailed(_) => "validation_failed",
+            Self::UserNotFound(_) => "user_not_found",
+            Self::RoleNotFound(_) => "role_not_found",
+            Self::PermissionDenied(_) => "permission_denied",
+            Self::Storage(_) => "storage_error",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CollectionNotFound(collection_id) => {
+                write!(f, "No collection found with ID {}.", collection_id)
+            }
+            Self::RecordNotFound(record_id) => {
+                write!(f, "No record found with ID {}.", record_id)
+            }
+            Self::IndexNotFound(name) => write!(f, "No index found with name {}.", name),
+            Self::DuplicateKey { index, key } => {
+                write!(
+                    f,
+                    "Index {} already holds a record with key {}.",
+                    index, key
+                )
+            }
+            Self::InvalidId => write!(f, "Record _id must be a non-empty string."),
+            Self::UserNotFound(username) => write!(f, "No user found with name {}.", username),
+            Self::RoleNotFound(name) => write!(f, "No role found with name {}.", name),
+            Self::InvalidIndex(reason)
+            | Self::ValidationFailed(reason)
+            | Self::PermissionDenied(reason) => {
+                write!(f, "{}", reason)
+            }
+            Self::Storage(reason) => write!(f, "Storage failed: {}", reason),
+            Self::Internal(reason) => write!(f, "Internal error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CoreError {}
+
+impl From<&anyhow::Error> for CoreError {
+    /// Classifies an error of a core operation, keeping core errors as they are. I/O errors
+    /// anywhere in the chain are storage failures.
+    fn from(err: &anyhow::Error) -> Self {
+        if let Some(core_err) = err.downcast_ref::<CoreError>() {
+            return core_err.clone();
+        }
+
+        if err.chain().any(|cause| cause.is::<io::Error>()) {
+            return Self::Storage(format!("{:#}", err));
+        }
+
+        Self::Internal(format!("{:#}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn codes_are_stable() {
+        let reason = || "reason".to_string();
+
+        for (err, code) in [
+            (
+                CoreError::CollectionNotFound(reason()),
+                "collection_not_found",
+            ),
+            (CoreError::RecordNotFound(reason()), "record_not_found"),
+            (CoreError::IndexNotFound(reason()), "index_not_found"),
+            (
+                CoreError::DuplicateKey {
+                    index: reason(),
+                    key: reason(),
+                },
+                "duplicate_key",
+            ),
+            (CoreError::InvalidId, "invalid_id"),
+            (CoreError::InvalidIndex(reason()), "invalid_index"),
+            (CoreError::ValidationFailed(reason()), "validation_failed"),
+            (CoreError::UserNotFound(reason()), "user_not_found"),
+            (CoreError::RoleNotFound(reason()), "role_not_found"),
+            (CoreError::PermissionDenied(reason()), "permission_denied"),
+            (CoreError::Storage(reason()), "storage_error"),
+            (CoreError::Internal(reason()), "internal_error"),
+        ] {
+            assert_eq!(err.code(), code);
+        }
+    }
+
+    #[test]
+    fn classifies_anyhow_errors() {
+        let core_err = CoreError::RecordNotFound("1".into());
+        assert_eq!(
+            CoreError::from(&anyhow::Error::from(core_err.clone())),
+            core_err
+        );
+
+        let io_err = io::Error::new(io::ErrorKind::PermissionDenied, "read only");
+        let err = Err::<(), _>(io_err)
+            .context("Could not write map.json")
+            .unwrap_err();
+        let classified = CoreError::from(&err);
+
+        assert_eq!(classified.code(), "storage_error");
+        assert!(classified.to_string().contains("read only"));
+
+        let err = serde_json::from_str::<u32>("nope").unwrap_err().into();
+        assert_eq!(CoreError::from(&err).code(), "internal_error");
+    }
+}
@@ -0,0 +1,1233 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+
+use crate::{
+    core::{
+        error::CoreError,
+        path::lookup_path,
+        query::{Condition, Filter, SortKey, compare_values},
+        record::{MoleculeCoreRecordsExt, Record},
+    },
+    molecule::Molecule,
+};
+
+/// Field every collection is uniquely indexed on by default.
+pub const ID_INDEX_FIELD: &str = "_id";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexDefinition {
+    /// Name the index is listed and dropped by.
+    pub name: String,
+    /// Dotted paths of the indexed fields. Indexes over several fields are ordered by the first
+    /// field, then the second, and so on.
+    pub fields: Vec<String>,
+    /// Reject records whose key is already held by another record.
+    #[serde(default)]
+    pub unique: bool,
+    /// Leave out records missing every indexed field.
+    #[serde(default)]
+    pub sparse: bool,
+    /// Only index records matching this filter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial: Option<Value>,
+    /// Expire records this many seconds after the unix timestamp (in seconds) held by the
+    /// indexed field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_after_secs: Option<u64>,
+}
+
+/// Options for creating an index, e.g. `{"unique": true, "partial": {"active": true}}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexOptions {
+    /// Defaults to the indexed fields joined by commas.
+    pub name: Option<String>,
+    #[serde(default)]
+    pub unique: bool,
+    #[serde(default)]
+    pub sparse: bool,
+    pub partial: Option<Value>,
+    pub expire_after_secs: Option<u64>,
+}
+
+impl IndexDefinition {
+    pub fn new(fields: Vec<String>, options: IndexOptions) -> Result<Self, CoreError> {
+        if fields.is_empty() {
+            return Err(CoreError::InvalidIndex(
+                "An index needs at least one field.".into(),
+            ));
+        }
+
+        if let Some(field) = fields
+            .iter()
+            .find(|f| f.is_empty() || f.split('.').any(str::is_empty))
+        {
+            return Err(CoreError::InvalidIndex(format!(
+                "Invalid index field: {}",
+                field
+            )));
+        }
+
+        if options.expire_after_secs.is_some() && fields.len() != 1 {
+            return Err(CoreError::InvalidIndex(
+                "TTL indexes must be on a single field.".into(),
+            ));
+        }
+
+        let name = options.name.unwrap_or_else(|| fields.join(","));
+
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(CoreError::InvalidIndex(format!(
+                "Invalid index name: {}",
+                name
+            )));
+        }
+
+        let definition = Self {
+            name,
+            fields,
+            unique: options.unique,
+            sparse: options.sparse,
+            partial: options.partial,
+            expire_after_secs: options.expire_after_secs,
+        };
+        definition.partial_filter()?;
+
+        Ok(definition)
+    }
+
+    /// The unique index on `_id` every collection has.
+    pub fn id() -> Self {
+        Self {
+            name: ID_INDEX_FIELD.into(),
+            fields: vec![ID_INDEX_FIELD.into()],
+            unique: true,
+            sparse: false,
+            partial: None,
+            expire_after_secs: None,
+        }
+    }
+
+    /// Filter matching the records expired at `now` (unix seconds), none unless this is a TTL
+    /// index. Records holding an array of timestamps expire with the earliest.
+    pub fn expired_filter(&self, now: f64) -> Result<Option<Filter>, CoreError> {
+        let Some(expire_after_secs) = self.expire_after_secs else {
+            return Ok(None);
+        };
+        let Some(cutoff) = Number::from_f64(now - expire_after_secs as f64) else {
+            return Ok(None);
+        };
+
+        let expired = Filter::Field(
+            self.fields[0].clone(),
+            vec![Condition::Lte(Value::Number(cutoff))],
+        );
+
+        Ok(Some(match self.partial_filter()? {
+            Some(partial) => Filter::And(vec![partial, expired]),
+            None => expired,
+        }))
+    }
+
+    /// Partial filters may only combine field conditions with `$and`, so that whether a query
+    /// is covered by the index can be told from the query's own field conditions.
+    fn partial_filter(&self) -> Result<Option<Filter>, CoreError> {
+        let Some(partial) = &self.partial else {
+            return Ok(None);
+        };
+
+        let filter =
+            Filter::parse(partial).map_err(|err| CoreError::InvalidIndex(err.to_string()))?;
+
+        if !is_conjunctive(&filter) {
+            return Err(CoreError::InvalidIndex(
+                "Partial index filters cannot use $or or $not.".into(),
+            ));
+        }
+
+        Ok(Some(filter))
+    }
+}
+
+fn is_conjunctive(filter: &Filter) -> bool {
+    match filter {
+        Filter::And(filters) => filters.iter().all(is_conjunctive),
+        Filter::Field(..) => true,
+        Filter::Or(_) | Filter::Not(_) => false,
+    }
+}
+
+/// Index key made of one value per indexed field, ordered field by field the same way records
+/// are sorted.
+#[derive(Debug, Clone)]
+struct IndexKey(Vec<Value>);
+
+impl PartialEq for IndexKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for IndexKey {}
+
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IndexKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| compare_values(a, b))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| self.0.len().cmp(&other.0.len()))
+    }
+}
+
+impl IndexKey {
+    fn starts_with(&self, prefix: &[Value]) -> bool {
+        self.0.len() >= prefix.len()
+            && self
+                .0
+                .iter()
+                .zip(prefix)
+                .all(|(a, b)| compare_values(a, b) == Ordering::Equal)
+    }
+
+    fn describe(&self) -> String {
+        match self.0.as_slice() {
+            [value] => value.to_string(),
+            values => Value::Array(values.to_vec()).to_string(),
+        }
+    }
+}
+
+/// An index mapping the values of one or more fields to the IDs of the records holding them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "PersistedIndex", into = "PersistedIndex")]
+pub struct Index {
+    pub definition: IndexDefinition,
+    partial: Option<Filter>,
+    /// Whether any record has held an array in an indexed field, in which case records can be
+    /// indexed under several keys.
+    multikey: bool,
+    entries: BTreeMap<IndexKey, BTreeSet<String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedIndex {
+    #[serde(flatten)]
+    definition: IndexDefinition,
+    #[serde(default)]
+    multikey: bool,
+    entries: Vec<(Vec<Value>, BTreeSet<String>)>,
+}
+
+impl TryFrom<PersistedIndex> for Index {
+    type Error = CoreError;
+
+    fn try_from(value: PersistedIndex) -> Result<Self, Self::Error> {
+        Ok(Self {
+            partial: value.definition.partial_filter()?,
+            definition: value.definition,
+            multikey: value.multikey,
+            entries: value
+                .entries
+                .into_iter()
+                .map(|(key, ids)| (IndexKey(key), ids))
+                .collect(),
+        })
+    }
+}
+
+impl From<Index> for PersistedIndex {
+    fn from(value: Index) -> Self {
+        Self {
+            definition: value.definition,
+            multikey: value.multikey,
+            entries: value
+                .entries
+                .into_iter()
+                .map(|(key, ids)| (key.0, ids))
+                .collect(),
+        }
+    }
+}
+
+impl Index {
+    pub fn build(definition: IndexDefinition, records: &[Record]) -> Result<Self, CoreError> {
+        let mut index = Self {
+            partial: definition.partial_filter()?,
+            definition,
+            multikey: false,
+            entries: BTreeMap::new(),
+        };
+
+        for record in records {
+            index.check(record)?;
+            index.insert(record);
+        }
+
+        Ok(index)
+    }
+
+    /// Keys a record is indexed under, one for every combination of its indexed values. Missing
+    /// fields are indexed as `null`, and arrays both as a whole and by each of their elements,
+    /// so that every record a filter on the fields could match is found through the index.
+    fn keys(&self, record: &Record) -> Vec<IndexKey> {
+        if self
+            .partial
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(record))
+        {
+            return Vec::new();
+        }
+
+        let values: Vec<_> = self
+            .definition
+            .fields
+            .iter()
+            .map(|field| lookup_path(record, field))
+            .collect();
+
+        if self.definition.sparse && values.iter().all(Option::is_none) {
+            return Vec::new();
+        }
+
+        values
+            .into_iter()
+            .fold(vec![Vec::new()], |keys, value| {
+                let components = match value {
+                    None => vec![Value::Null],
+                    Some(Value::Array(items)) => items
+                        .iter()
+                        .cloned()
+                        .chain([Value::Array(items.clone())])
+                        .collect(),
+                    Some(value) => vec![value.clone()],
+                };
+
+                keys.iter()
+                    .flat_map(|key| {
+                        components.iter().map(move |component| {
+                            let mut key = key.clone();
+                            key.push(component.clone());
+                            key
+                        })
+                    })
+                    .collect()
+            })
+            .into_iter()
+            .map(IndexKey)
+            .collect()
+    }
+
+    /// Fails if the record would share a key of a unique index with a record already in it.
+    fn check(&self, record: &Record) -> Result<(), CoreError> {
+        if !self.definition.unique {
+            return Ok(());
+        }
+
+        for key in self.keys(record) {
+            if self.entries.contains_key(&key) {
+                return Err(CoreError::DuplicateKey {
+                    index: self.definition.name.clone(),
+                    key: key.describe(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert(&mut self, record: &Record) {
+        let Some(record_id) = record_id(record) else {
+            return;
+        };
+
+        if self
+            .definition
+            .fields
+            .iter()
+            .any(|field| lookup_path(record, field).is_some_and(Value::is_array))
+        {
+            self.multikey = true;
+        }
+
+        for key in self.keys(record) {
+            self.entries
+                .entry(key)
+                .or_default()
+                .insert(record_id.to_string());
+        }
+    }
+
+    fn remove(&mut self, record: &Record) {
+        let Some(record_id) = record_id(record) else {
+            return;
+        };
+
+        for key in self.keys(record) {
+            if let Some(ids) = self.entries.get_mut(&key) {
+                ids.remove(record_id);
+
+                if ids.is_empty() {
+                    self.entries.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Whether the index holds every record of the collection that could match a query with
+    /// these field conditions.
+    fn covers(&self, conditions: &HashMap<&str, Vec<&Condition>>) -> bool {
+        let Some(partial) = &self.partial else {
+            return true;
+        };
+
+        partial.conjuncts().into_iter().all(|(path, required)| {
+            conditions
+                .get(path)
+                .is_some_and(|conds| required.iter().all(|c| conds.contains(&c)))
+        })
+    }
+
+    /// IDs of every record that could satisfy the conditions, or `None` if the index can't
+    /// answer them. Equality conditions on the leading fields narrow the scan down to a prefix
+    /// of the index, and the conditions on the field right after it are checked on the keys.
+    fn lookup(&self, conditions: &HashMap<&str, Vec<&Condition>>) -> Option<BTreeSet<String>> {
+        if !self.covers(conditions) {
+            return None;
+        }
+
+        let field_conditions = |field: &String| -> Vec<&Condition> {
+            conditions
+                .get(field.as_str())
+                .into_iter()
+                .flatten()
+                .copied()
+                .filter(|c| {
+                    matches!(
+                        c,
+                        Condition::Eq(_)
+                            | Condition::In(_)
+                            | Condition::Gt(_)
+                            | Condition::Gte(_)
+                            | Condition::Lt(_)
+                            | Condition::Lte(_)
+                    )
+                })
+                .collect()
+        };
+
+        let mut prefix = Vec::new();
+        let mut used = Vec::new();
+
+        for field in &self.definition.fields {
+            let Some(eq @ Condition::Eq(value)) = field_conditions(field)
+                .into_iter()
+                .find(|c| matches!(c, Condition::Eq(_)))
+            else {
+                break;
+            };
+
+            prefix.push(value.clone());
+            used.push(eq);
+        }
+
+        let position = prefix.len();
+        let mut range = self
+            .definition
+            .fields
+            .get(position)
+            .map(field_conditions)
+            .unwrap_or_default();
+
+        // Each key of a multikey index holds a single array element, while a filter's conditions
+        // may each be matched by a different element, so only one of them can narrow the scan.
+        if self.multikey {
+            range.truncate(1);
+        }
+
+        used.extend(&range);
+
+        // Sparse indexes leave out records missing the indexed fields, which is only fine if
+        // none of those could have matched.
+        if used.is_empty() || (self.definition.sparse && used.iter().all(|c| c.matches(None))) {
+            return None;
+        }
+
+        let mut start = prefix.clone();
+        let mut upper = None;
+
+        for condition in &range {
+            match condition {
+                Condition::Gt(bound) | Condition::Gte(bound) if start.len() == position => {
+                    start.push(bound.clone());
+                }
+                Condition::Lt(bound) | Condition::Lte(bound) => upper = Some(bound),
+                _ => {}
+            }
+        }
+
+        let ids = self
+            .entries
+            .range((Bound::Included(IndexKey(start)), Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .take_while(|(key, _)| {
+                upper.is_none_or(|upper| {
+                    key.0
+                        .get(position)
+                        .is_none_or(|v| compare_values(v, upper) != Ordering::Greater)
+                })
+            })
+            .filter(|(key, _)| range.iter().all(|c| c.matches(key.0.get(position))))
+            .flat_map(|(_, ids)| ids.iter().cloned())
+            .collect();
+
+        Some(ids)
+    }
+
+    /// Whether walking the index yields every record exactly once, ordered by the sort keys.
+    fn can_sort(&self, sort: &[SortKey]) -> bool {
+        !self.multikey
+            && !self.definition.sparse
+            && self.partial.is_none()
+            && sort.len() <= self.definition.fields.len()
+            && sort
+                .iter()
+                .zip(&self.definition.fields)
+                .all(|(key, field)| &key.path == field)
+    }
+}
+
+/// Every index of a collection, always including the unique one on `_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CollectionIndexes(Vec<Index>);
+
+impl CollectionIndexes {
+    pub fn new(records: &[Record]) -> Result<Self, CoreError> {
+        Ok(Self(vec![Index::build(IndexDefinition::id(), records)?]))
+    }
+
+    /// Builds the indexes with the given definitions over `records`.
+    pub fn build(definitions: Vec<IndexDefinition>, records: &[Record]) -> Result<Self, CoreError> {
+        definitions
+            .into_iter()
+            .map(|definition| Index::build(definition, records))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    pub fn definitions(&self) -> Vec<IndexDefinition> {
+        self.0.iter().map(|i| i.definition.clone()).collect()
+    }
+
+    /// Builds a new index over `records`, returning `false` if the exact same index exists.
+    pub fn create(
+        &mut self,
+        definition: IndexDefinition,
+        records: &[Record],
+    ) -> Result<bool, CoreError> {
+        if let Some(existing) = self.0.iter().find(|i| i.definition.name == definition.name) {
+            if existing.definition == definition {
+                return Ok(false);
+            }
+
+            return Err(CoreError::InvalidIndex(format!(
+                "An index named {} already exists with a different definition.",
+                definition.name
+            )));
+        }
+
+        self.0.push(Index::build(definition, records)?);
+        Ok(true)
+    }
+
+    /// Drops an index by name, returning whether it existed.
+    pub fn drop(&mut self, name: &str) -> Result<bool, CoreError> {
+        if name == ID_INDEX_FIELD {
+            return Err(CoreError::InvalidIndex(
+                "The _id index cannot be dropped.".into(),
+            ));
+        }
+
+        let count = self.0.len();
+        self.0.retain(|i| i.definition.name != name);
+
+        Ok(self.0.len() != count)
+    }
+
+    /// Indexes a new record, failing without indexing it if it violates a unique index.
+    pub fn insert(&mut self, record: &Record) -> Result<(), CoreError> {
+        for index in &self.0 {
+            index.check(record)?;
+        }
+
+        for index in &mut self.0 {
+            index.insert(record);
+        }
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, record: &Record) {
+        for index in &mut self.0 {
+            index.remove(record);
+        }
+    }
+
+    /// Re-indexes a changed record, leaving the indexes as they were if the changed record
+    /// violates a unique index.
+    pub fn replace(&mut self, previous: &Record, updated: &Record) -> Result<(), CoreError> {
+        self.remove(previous);
+
+        if let Err(err) = self.insert(updated) {
+            for index in &mut self.0 {
+                index.insert(previous);
+            }
+
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// IDs of every record that could match the filter, narrowed down using the indexes on
+    /// fields it requires to be equal to or within a range of values. `None` means no index
+    /// applies and every record has to be checked.
+    pub fn candidates(&self, filter: &Filter) -> Option<BTreeSet<String>> {
+        let mut conditions: HashMap<&str, Vec<&Condition>> = HashMap::new();
+
+        for (path, conds) in filter.conjuncts() {
+            conditions.entry(path).or_default().extend(conds);
+        }
+
+        self.0
+            .iter()
+            .filter_map(|index| index.lookup(&conditions))
+            .reduce(|current, ids| current.intersection(&ids).cloned().collect())
+    }
+
+    /// Orders records by walking an index over the sorted fields, returning `false` if no
+    /// index can be used. Records that sort equally keep their relative order, exactly as if
+    /// they were sorted in memory.
+    pub fn sort(&self, records: &mut Vec<Record>, sort: &[SortKey]) -> bool {
+        let Some(first) = sort.first() else {
+            return false;
+        };
+
+        if sort.iter().any(|key| key.descending != first.descending) {
+            return false;
+        }
+
+        let Some(index) = self.0.iter().find(|i| i.can_sort(sort)) else {
+            return false;
+        };
+
+        let positions: HashMap<&str, usize> = records
+            .iter()
+            .enumerate()
+            .filter_map(|(position, record)| Some((record_id(record)?, position)))
+            .collect();
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut group_key: Option<&[Value]> = None;
+
+        for (key, ids) in &index.entries {
+            let head = &key.0[..sort.len()];
+
+            if group_key.is_none_or(|k| !IndexKey(head.to_vec()).starts_with(k)) {
+                groups.push(Vec::new());
+                group_key = Some(head);
+            }
+
+            if let Some(group) = groups.last_mut() {
+                group.extend(ids.iter().filter_map(|id| positions.get(id.as_str())));
+            }
+        }
+
+        for group in &mut groups {
+            group.sort_unstable();
+        }
+
+        if first.descending {
+            groups.reverse();
+        }
+
+        let order = groups.concat();
+
+        if order.len() != records.len() {
+            return false;
+        }
+
+        let mut slots: Vec<_> = records.drain(..).map(Some).collect();
+        records.extend(
+            order
+                .into_iter()
+                .filter_map(|position| slots[position].take()),
+        );
+
+        true
+    }
+}
+
+fn record_id(record: &Record) -> Option<&str> {
+    record.get("_id").and_then(Value::as_str)
+}
+
+pub trait MoleculeCoreIndexApi {
+    async fn create_index(
+        &self,
+        collection_id: String,
+        fields: Vec<String>,
+        options: IndexOptions,
+    ) -> Result<String>;
+    async fn drop_index(&self, collection_id: String, name: String) -> Result<bool>;
+    async fn list_indexes(&self, collection_id: String) -> Result<Vec<IndexDefinition>>;
+}
+
+impl MoleculeCoreIndexApi for Molecule {
+    async fn create_index(
+        &self,
+        collection_id: String,
+        fields: Vec<String>,
+        options: IndexOptions,
+    ) -> Result<String> {
+        let definition = IndexDefinition::new(fields, options)?;
+        let name = definition.name.clone();
+        let _guard = self.lock_collection_for_write(&collection_id).await;
+        let mut collection = self.take_collection(&collection_id).await?;
+
+        if !collection.indexes.create(definition, &collection.records)? {
+            self.restore_collection(&collection_id, collection);
+            return Ok(name);
+        }
+
+        self.persist_indexes(&collection_id, collection).await?;
+        log::info!("Created index {} on collection: {}", name, collection_id);
+
+        Ok(name)
+    }
+
+    async fn drop_index(&self, collection_id: String, name: String) -> Result<bool> {
+        let _guard = self.lock_collection_for_write(&collection_id).await;
+        let mut collection = self.take_collection(&collection_id).await?;
+
+        if !collection.indexes.drop(&name)? {
+            self.restore_collection(&collection_id, collection);
+            return Ok(false);
+        }
+
+        self.persist_indexes(&collection_id, collection).await?;
+        log::info!("Dropped index {} on collection: {}", name, collection_id);
+
+        Ok(true)
+    }
+
+    async fn list_indexes(&self, collection_id: String) -> Result<Vec<IndexDefinition>> {
+        let _guard = self.collection_lock(&collection_id).read_owned().await;
+        let collection = self.load_collection(&collection_id).await?;
+
+        Ok(collection.indexes.definitions())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::core::collection::MoleculeCoreCollectionApi;
+    use crate::core::query::{Filter, QueryOptions};
+    use crate::core::record::MoleculeCoreRecordsApi;
+    use crate::core::update::Update;
+
+    async fn find_ids(molecule: &Molecule, collection_id: &str, filter: &Value) -> Vec<String> {
+        let mut ids: Vec<String> = molecule
+            .find_records(
+                collection_id.to_string(),
+                Filter::parse(filter).unwrap(),
+                QueryOptions::default(),
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|record| record["_id"].as_str().unwrap().to_string())
+            .collect();
+
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn multikey_ranges_match_unindexed_results() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("posts".into()).await.unwrap();
+
+        for record in [
+            json!({"_id": "split", "tags": [1, 10]}),
+            json!({"_id": "inside", "tags": [6]}),
+            json!({"_id": "above", "tags": [20, 30]}),
+            json!({"_id": "scalar", "tags": 7}),
+            json!({"_id": "missing"}),
+        ] {
+            molecule
+                .create_record(
+                    collection_id.clone(),
+                    serde_json::from_value(record).unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let filters = [
+            json!({"tags": {"$gt": 5, "$lt": 8}}),
+            json!({"tags": {"$gte": 1, "$lte": 6}}),
+            json!({"tags": {"$eq": 1, "$gt": 5}}),
+            json!({"tags": {"$in": [1, 30], "$lt": 8}}),
+            json!({"tags": {"$gt": 25}}),
+        ];
+        let mut unindexed = Vec::new();
+
+        for filter in &filters {
+            unindexed.push(find_ids(&molecule, &collection_id, filter).await);
+        }
+
+        molecule
+            .create_index(
+                collection_id.clone(),
+                vec!["tags".into()],
+                IndexOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        for (filter, expected) in filters.iter().zip(unindexed) {
+            assert_eq!(
+                find_ids(&molecule, &collection_id, filter).await,
+                expected,
+                "{}",
+                filter
+            );
+        }
+
+        assert_eq!(
+            find_ids(&molecule, &collection_id, &filters[0]).await,
+            vec!["inside", "scalar", "split"]
+        );
+    }
+
+    fn parse_records(value: Value) -> Vec<Record> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    /// The default indexes of `records` plus one built from `fields` and `options`.
+    fn indexes_with(records: &[Record], fields: &[&str], options: Value) -> CollectionIndexes {
+        let mut indexes = CollectionIndexes::new(records).unwrap();
+        let definition = IndexDefinition::new(
+            fields.iter().map(|f| f.to_string()).collect(),
+            serde_json::from_value(options).unwrap(),
+        )
+        .unwrap();
+
+        indexes.create(definition, records).unwrap();
+        indexes
+    }
+
+    fn candidates(indexes: &CollectionIndexes, filter: Value) -> Option<Vec<String>> {
+        indexes
+            .candidates(&Filter::parse(&filter).unwrap())
+            .map(|ids| ids.into_iter().collect())
+    }
+
+    #[tokio::test]
+    async fn unique_index_rejects_duplicates() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+
+        for record in [
+            json!({"_id": "alice", "email": "alice@example.com"}),
+            json!({"_id": "bob", "email": "bob@example.com"}),
+        ] {
+            molecule
+                .create_record(
+                    collection_id.clone(),
+                    serde_json::from_value(record).unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        molecule
+            .create_index(
+                collection_id.clone(),
+                vec!["email".into()],
+                IndexOptions {
+                    name: Some("unique_email".into()),
+                    unique: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let err = molecule
+            .create_record(
+                collection_id.clone(),
+                serde_json::from_value(json!({"_id": "carol", "email": "bob@example.com"}))
+                    .unwrap(),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            CoreError::from(&err),
+            CoreError::DuplicateKey { index, .. } if index == "unique_email"
+        ));
+
+        let err = molecule
+            .update_record(
+                collection_id.clone(),
+                "alice".into(),
+                Update::parse(&json!({"$set": {"email": "bob@example.com"}})).unwrap(),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            CoreError::from(&err),
+            CoreError::DuplicateKey { index, .. } if index == "unique_email"
+        ));
+        assert_eq!(
+            find_ids(
+                &molecule,
+                &collection_id,
+                &json!({"email": "alice@example.com"})
+            )
+            .await,
+            vec!["alice"]
+        );
+        assert_eq!(
+            find_ids(&molecule, &collection_id, &json!({"_id": "carol"})).await,
+            Vec::<String>::new()
+        );
+
+        let err = molecule
+            .create_index(
+                collection_id,
+                vec!["kind".into()],
+                IndexOptions {
+                    unique: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap_err();
+
+        // Both records are missing the field, which is indexed as null.
+        assert_eq!(CoreError::from(&err).code(), "duplicate_key");
+    }
+
+    #[test]
+    fn compound_index_narrows_by_prefix() {
+        let records = parse_records(json!([
+            {"_id": "1", "city": "Paris", "age": 20},
+            {"_id": "2", "city": "Paris", "age": 40},
+            {"_id": "3", "city": "Rome", "age": 40},
+            {"_id": "4", "city": "Paris"},
+        ]));
+        let indexes = indexes_with(&records, &["city", "age"], json!({}));
+
+        assert_eq!(
+            candidates(&indexes, json!({"city": "Paris"})),
+            Some(vec!["1".into(), "2".into(), "4".into()])
+        );
+        assert_eq!(
+            candidates(&indexes, json!({"city": "Paris", "age": {"$gt": 30}})),
+            Some(vec!["2".into()])
+        );
+        assert_eq!(
+            candidates(&indexes, json!({"city": "Paris", "age": 20})),
+            Some(vec!["1".into()])
+        );
+        assert_eq!(
+            candidates(&indexes, json!({"city": {"$in": ["Rome"]}})),
+            Some(vec!["3".into()])
+        );
+        // Only leading fields can narrow the scan.
+        assert_eq!(candidates(&indexes, json!({"age": 40})), None);
+        assert_eq!(
+            candidates(
+                &indexes,
+                json!({"$or": [{"city": "Paris"}, {"city": "Rome"}]})
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn sorts_through_index() {
+        let records = parse_records(json!([
+            {"_id": "1", "city": "Rome", "age": 20},
+            {"_id": "2", "city": "Paris", "age": 40},
+            {"_id": "3", "city": "Paris", "age": 30},
+            {"_id": "4", "age": 50},
+        ]));
+        let indexes = indexes_with(&records, &["city", "age"], json!({}));
+        let sorted = |sort: Value| -> Option<Vec<String>> {
+            let options = QueryOptions::parse(&json!({"sort": sort})).unwrap();
+            let mut sorted = records.clone();
+
+            indexes.sort(&mut sorted, &options.sort).then(|| {
+                sorted
+                    .iter()
+                    .map(|r| r["_id"].as_str().unwrap().to_string())
+                    .collect()
+            })
+        };
+
+        assert_eq!(
+            sorted(json!(["city", "age"])),
+            Some(vec!["4".into(), "3".into(), "2".into(), "1".into()])
+        );
+        assert_eq!(
+            sorted(json!(["-city", "-age"])),
+            Some(vec!["1".into(), "2".into(), "3".into(), "4".into()])
+        );
+        // Records with equal sort keys keep their relative order.
+        assert_eq!(
+            sorted(json!(["city"])),
+            Some(vec!["4".into(), "2".into(), "3".into(), "1".into()])
+        );
+        assert_eq!(sorted(json!(["age"])), None);
+        assert_eq!(sorted(json!(["city", "-age"])), None);
+
+        for sort in [
+            json!(["city", "age"]),
+            json!(["-city"]),
+            json!(["-city", "-age"]),
+        ] {
+            let options = QueryOptions::parse(&json!({"sort": sort})).unwrap();
+            let mut indexed = records.clone();
+
+            assert!(indexes.sort(&mut indexed, &options.sort));
+            assert_eq!(indexed, options.apply(records.clone()), "{}", sort);
+        }
+    }
+
+    #[test]
+    fn sparse_index_skips_records_missing_fields() {
+        let records = parse_records(json!([
+            {"_id": "1", "email": "a@example.com"},
+            {"_id": "2"},
+            {"_id": "3"},
+        ]));
+        let indexes = indexes_with(
+            &records,
+            &["email"],
+            json!({"sparse": true, "unique": true}),
+        );
+
+        assert_eq!(
+            candidates(&indexes, json!({"email": "a@example.com"})),
+            Some(vec!["1".into()])
+        );
+        // Records missing the field could match, so the index can't be used.
+        assert_eq!(candidates(&indexes, json!({"email": null})), None);
+        assert_eq!(
+            candidates(&indexes, json!({"email": {"$exists": false}})),
+            None
+        );
+    }
+
+    #[test]
+    fn partial_index_skips_non_matching_records() {
+        let records = parse_records(json!([
+            {"_id": "1", "email": "a@example.com", "active": true},
+            {"_id": "2", "email": "a@example.com", "active": false},
+            {"_id": "3", "email": "a@example.com"},
+        ]));
+        let mut indexes = indexes_with(
+            &records,
+            &["email"],
+            json!({"unique": true, "partial": {"active": true}}),
+        );
+
+        assert_eq!(
+            candidates(&indexes, json!({"email": "a@example.com", "active": true})),
+            Some(vec!["1".into()])
+        );
+        // Filters that don't repeat the partial filter may match records left out of it.
+        assert_eq!(
+            candidates(&indexes, json!({"email": "a@example.com"})),
+            None
+        );
+
+        let inactive = parse_records(json!([{"_id": "4", "email": "a@example.com"}])).remove(0);
+        let active = parse_records(json!([{"_id": "5", "email": "a@example.com", "active": true}]))
+            .remove(0);
+
+        assert!(indexes.insert(&inactive).is_ok());
+        assert_eq!(indexes.insert(&active).unwrap_err().code(), "duplicate_key");
+    }
+
+    #[tokio::test]
+    async fn creates_lists_and_drops_indexes() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+        let by_email = IndexOptions {
+            unique: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            molecule.list_indexes(collection_id.clone()).await.unwrap(),
+            vec![IndexDefinition::id()]
+        );
+
+        let err = molecule
+            .drop_index(collection_id.clone(), "_id".into())
+            .await
+            .unwrap_err();
+
+        assert_eq!(CoreError::from(&err).code(), "invalid_index");
+
+        for _ in 0..2 {
+            assert_eq!(
+                molecule
+                    .create_index(
+                        collection_id.clone(),
+                        vec!["email".into()],
+                        by_email.clone()
+                    )
+                    .await
+                    .unwrap(),
+                "email"
+            );
+        }
+
+        let err = molecule
+            .create_index(
+                collection_id.clone(),
+                vec!["email".into()],
+                IndexOptions::default(),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(CoreError::from(&err).code(), "invalid_index");
+        assert_eq!(
+            molecule.list_indexes(collection_id.clone()).await.unwrap(),
+            vec![
+                IndexDefinition::id(),
+                IndexDefinition::new(vec!["email".into()], by_email).unwrap()
+            ]
+        );
+        assert!(
+            molecule
+                .drop_index(collection_id.clone(), "email".into())
+                .await
+                .unwrap()
+        );
+        assert!(
+            !molecule
+                .drop_index(collection_id.clone(), "email".into())
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            molecule.list_indexes(collection_id).await.unwrap(),
+            vec![IndexDefinition::id()]
+        );
+    }
+
+    #[tokio::test]
+    async fn maintains_indexes_across_writes() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+        let indexed = |filter: Value| {
+            let molecule = &molecule;
+            let collection_id = &collection_id;
+
+            async move {
+                let collection = molecule.load_collection(collection_id).await.unwrap();
+
+                candidates(&collection.indexes, filter)
+            }
+        };
+
+        molecule
+            .create_index(
+                collection_id.clone(),
+                vec!["n".into()],
+                IndexOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        for (id, n) in [("a", 1), ("b", 2), ("c", 2)] {
+            molecule
+                .create_record(
+                    collection_id.clone(),
+                    serde_json::from_value(json!({"_id": id, "n": n})).unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            indexed(json!({"n": 2})).await,
+            Some(vec!["b".into(), "c".into()])
+        );
+        assert_eq!(indexed(json!({"_id": "a"})).await, Some(vec!["a".into()]));
+
+        molecule
+            .update_record(
+                collection_id.clone(),
+                "b".into(),
+                Update::parse(&json!({"$set": {"n": 3}})).unwrap(),
+            )
+            .await
+            .unwrap();
+        molecule
+            .replace_record(
+                collection_id.clone(),
+                "a".into(),
+                serde_json::from_value(json!({"n": 2})).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            indexed(json!({"n": 2})).await,
+            Some(vec!["a".into(), "c".into()])
+        );
+        assert_eq!(indexed(json!({"n": 3})).await, Some(vec!["b".into()]));
+        assert_eq!(indexed(json!({"n": 1})).await, Some(Vec::new()));
+
+        molecule
+            .delete_record(collection_id.clone(), "c".into())
+            .await
+            .unwrap();
+        molecule
+            .delete_records(
+                collection_id.clone(),
+                Filter::parse(&json!({"n": 3})).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(indexed(json!({"n": 2})).await, Some(vec!["a".into()]));
+        assert_eq!(indexed(json!({"n": 3})).await, Some(Vec::new()));
+        assert_eq!(indexed(json!({"_id": "c"})).await, Some(Vec::new()));
+    }
+}
@@ -1,2 +1,13 @@
+pub mod atomic;
+pub mod cache;
 pub mod collection;
+pub mod consistency;
+pub mod data_dir;
+pub mod error;
+pub mod index;
+pub mod path;
+pub mod query;
 pub mod record;
+pub mod storage;
+pub mod update;
+pub mod wal;
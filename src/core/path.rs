@@ -0,0 +1,92 @@
+use serde_json::{Map, Value};
+
+use crate::core::record::Record;
+
+/// Resolves a dotted path such as `address.city` or `tags.0` inside a record.
+pub fn lookup_path<'a>(record: &'a Record, path: &str) -> Option<&'a Value> {
+    let mut segments = path.split('.');
+    let mut current = record.get(segments.next()?)?;
+
+    for segment in segments {
+        current = match current {
+            Value::Object(map) => map.get(segment)?,
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    Some(current)
+}
+
+/// Sets the value at a dotted path, creating missing intermediate objects. Returns `false`
+/// without modifying the record if an intermediate value is neither an object nor an array
+/// with the indexed element.
+pub fn set_path(record: &mut Record, path: &str, value: Value) -> bool {
+    let Some((first, rest)) = path.split_once('.') else {
+        record.insert(path.to_string(), value);
+        return true;
+    };
+
+    let parent = record
+        .entry(first.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+
+    set_nested(parent, rest, value)
+}
+
+fn set_nested(current: &mut Value, path: &str, value: Value) -> bool {
+    let (segment, rest) = match path.split_once('.') {
+        Some((segment, rest)) => (segment, Some(rest)),
+        None => (path, None),
+    };
+
+    let slot = match current {
+        Value::Object(map) => match rest {
+            Some(_) => map
+                .entry(segment.to_string())
+                .or_insert_with(|| Value::Object(Map::new())),
+            None => {
+                map.insert(segment.to_string(), value);
+                return true;
+            }
+        },
+        Value::Array(items) => match segment.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+            Some(item) => item,
+            None => return false,
+        },
+        _ => return false,
+    };
+
+    match rest {
+        Some(rest) => set_nested(slot, rest, value),
+        None => {
+            *slot = value;
+            true
+        }
+    }
+}
+
+/// Removes the value at a dotted path, returning it if it existed. Array elements are never
+/// removed, since that would shift the indices of the elements after them.
+pub fn remove_path(record: &mut Record, path: &str) -> Option<Value> {
+    let Some((first, rest)) = path.split_once('.') else {
+        return record.remove(path);
+    };
+
+    let mut current = record.get_mut(first)?;
+    let mut segments = rest.split('.').peekable();
+
+    while let Some(segment) = segments.next() {
+        if segments.peek().is_none() {
+            return current.as_object_mut()?.remove(segment);
+        }
+
+        current = match current {
+            Value::Object(map) => map.get_mut(segment)?,
+            Value::Array(items) => items.get_mut(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    None
+}
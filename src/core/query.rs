@@ -0,0 +1,717 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::core::path::{lookup_path, remove_path, set_path};
+use crate::core::record::Record;
+
+/// A parsed record filter, e.g. `{"age": {"$gte": 18}, "$or": [{"role": "admin"}, {"x": 1}]}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// Matches when every inner filter matches. An empty `And` matches everything.
+    And(Vec<Filter>),
+    /// Matches when at least one inner filter matches.
+    Or(Vec<Filter>),
+    /// Matches when the inner filter does not.
+    Not(Box<Filter>),
+    /// Matches when the value at a (possibly dotted) path satisfies every condition.
+    Field(String, Vec<Condition>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Eq(Value),
+    Ne(Value),
+    Gt(Value),
+    Gte(Value),
+    Lt(Value),
+    Lte(Value),
+    In(Vec<Value>),
+    Nin(Vec<Value>),
+    Exists(bool),
+    /// Field level `$not`, matches when the inner conditions don't all hold.
+    Not(Vec<Condition>),
+}
+
+impl Filter {
+    pub fn parse(value: &Value) -> Result<Self> {
+        let Value::Object(entries) = value else {
+            bail!("Filter must be a JSON object.");
+        };
+
+        let mut filters = Vec::with_capacity(entries.len());
+
+        for (key, value) in entries {
+            let filter = match key.as_str() {
+                "$and" => Self::And(Self::parse_list(key, value)?),
+                "$or" => Self::Or(Self::parse_list(key, value)?),
+                "$not" => Self::Not(Box::new(Self::parse(value)?)),
+                op if op.starts_with('$') => bail!("Unsupported filter operator: {}", op),
+                path => Self::Field(path.to_string(), Condition::parse_all(value)?),
+            };
+
+            filters.push(filter);
+        }
+
+        if filters.len() == 1 {
+            return Ok(filters.remove(0));
+        }
+
+        Ok(Self::And(filters))
+    }
+
+    fn parse_list(op: &str, value: &Value) -> Result<Vec<Self>> {
+        let Value::Array(items) = value else {
+            bail!("Operator {} expects an array of filters.", op);
+        };
+
+        if items.is_empty() {
+            bail!("Operator {} expects at least one filter.", op);
+        }
+
+        items.iter().map(Self::parse).collect()
+    }
+
+    /// Builds the record an upsert starts from, made up of every field the filter requires to
+    /// equal a specific value.
+    pub fn equality_seed(&self) -> Record {
+        let mut seed = Record::new();
+        self.collect_equalities(&mut seed);
+        seed
+    }
+
+    fn collect_equalities(&self, seed: &mut Record) {
+        match self {
+            Self::And(filters) => filters.iter().for_each(|f| f.collect_equalities(seed)),
+            Self::Field(path, conditions) => {
+                for condition in conditions {
+                    if let Condition::Eq(value) = condition {
+                        set_path(seed, path, value.clone());
+                    }
+                }
+            }
+            Self::Or(_) | Self::Not(_) => {}
+        }
+    }
+
+    pub fn matches(&self, record: &Record) -> bool {
+        match self {
+            Self::And(filters) => filters.iter().all(|f| f.matches(record)),
+            Self::Or(filters) => filters.iter().any(|f| f.matches(record)),
+            Self::Not(filter) => !filter.matches(record),
+            Self::Field(path, conditions) => {
+                let value = lookup_path(record, path);
+                conditions.iter().all(|c| c.matches(value))
+            }
+        }
+    }
+
+    /// Field conditions every matching record has to satisfy, i.e. the ones not nested under an
+    /// `$or` or `$not`.
+    pub fn conjuncts(&self) -> Vec<(&str, &[Condition])> {
+        match self {
+            Self::And(filters) => filters.iter().flat_map(Self::conjuncts).collect(),
+            Self::Field(path, conditions) => vec![(path.as_str(), conditions.as_slice())],
+            Self::Or(_) | Self::Not(_) => Vec::new(),
+        }
+    }
+}
+
+/// Sorting, projection and pagination applied to the records a query returns, e.g.
+/// `{"sort": ["-age", "name"], "projection": {"name": 1}, "skip": 20, "limit": 10}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryOptions {
+    pub sort: Vec<SortKey>,
+    pub projection: Option<Projection>,
+    pub skip: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub path: String,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Projection {
+    /// Keep only these paths, plus `_id` unless `keep_id` is `false`.
+    Include { paths: Vec<String>, keep_id: bool },
+    /// Drop these paths and keep everything else.
+    Exclude(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawQueryOptions {
+    #[serde(default)]
+    sort: Vec<String>,
+    projection: Option<HashMap<String, Value>>,
+    #[serde(default)]
+    skip: usize,
+    limit: Option<usize>,
+}
+
+impl QueryOptions {
+    pub fn parse(value: &Value) -> Result<Self> {
+        let raw: RawQueryOptions = serde_json::from_value(value.clone())?;
+        let sort = raw
+            .sort
+            .into_iter()
+            .map(|key| match key.strip_prefix('-') {
+                Some(path) => SortKey {
+                    path: path.to_string(),
+                    descending: true,
+                },
+                None => SortKey {
+                    path: key.strip_prefix('+').unwrap_or(&key).to_string(),
+                    descending: false,
+                },
+            })
+            .collect();
+        let projection = raw.projection.map(Projection::parse).transpose()?;
+
+        Ok(Self {
+            sort,
+            projection,
+            skip: raw.skip,
+            limit: raw.limit,
+        })
+    }
+
+    /// Sorts, paginates and projects records in that order.
+    pub fn apply(&self, mut records: Vec<Record>) -> Vec<Record> {
+        if !self.sort.is_empty() {
+            records.sort_by(|a, b| self.compare(a, b));
+        }
+
+        self.paginate(records)
+    }
+
+    /// Paginates and projects records that are already in sorted order.
+    pub fn paginate(&self, records: Vec<Record>) -> Vec<Record> {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let records = records.into_iter().skip(self.skip).take(limit);
+
+        match &self.projection {
+            Some(projection) => records.map(|r| projection.apply(r)).collect(),
+            None => records.collect(),
+        }
+    }
+
+    fn compare(&self, a: &Record, b: &Record) -> Ordering {
+        self.sort
+            .iter()
+            .map(|key| {
+                let null = Value::Null;
+                let a = lookup_path(a, &key.path).unwrap_or(&null);
+                let b = lookup_path(b, &key.path).unwrap_or(&null);
+                let ordering = compare_values(a, b);
+
+                if key.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl Projection {
+    /// Parses a projection such as `{"name": 1, "email": 1}` or `{"password": 0}`. Inclusion and
+    /// exclusion can't be mixed, except for excluding `_id` from an inclusion projection.
+    fn parse(fields: HashMap<String, Value>) -> Result<Self> {
+        let mut included = Vec::new();
+        let mut excluded = Vec::new();
+
+        for (path, flag) in fields {
+            let include = match flag {
+                Value::Bool(include) => include,
+                Value::Number(n) if n.as_i64() == Some(0) => false,
+                Value::Number(n) if n.as_i64() == Some(1) => true,
+                _ => bail!("Projection for {} must be 0, 1 or a boolean.", path),
+            };
+
+            if include {
+                included.push(path);
+            } else {
+                excluded.push(path);
+            }
+        }
+
+        included.sort();
+        excluded.sort();
+
+        if included.is_empty() {
+            return Ok(Self::Exclude(excluded));
+        }
+
+        match excluded.as_slice() {
+            [] => Ok(Self::Include {
+                paths: included,
+                keep_id: true,
+            }),
+            [id] if id == "_id" => Ok(Self::Include {
+                paths: included,
+                keep_id: false,
+            }),
+            _ => bail!("Projection cannot mix included and excluded fields other than _id."),
+        }
+    }
+
+    fn apply(&self, mut record: Record) -> Record {
+        match self {
+            Self::Include { paths, keep_id } => {
+                let mut projected = Record::new();
+
+                if *keep_id && let Some(id) = record.remove("_id") {
+                    projected.insert("_id".into(), id);
+                }
+
+                for path in paths {
+                    if let Some(value) = lookup_path(&record, path) {
+                        set_path(&mut projected, path, value.clone());
+                    }
+                }
+
+                projected
+            }
+            Self::Exclude(paths) => {
+                for path in paths {
+                    remove_path(&mut record, path);
+                }
+
+                record
+            }
+        }
+    }
+}
+
+impl Condition {
+    /// Parses the right hand side of a field filter. Objects made up only of `$` operators
+    /// are treated as conditions, anything else is an implicit `$eq`.
+    pub fn parse_all(value: &Value) -> Result<Vec<Self>> {
+        match value {
+            Value::Object(ops) if !ops.is_empty() && ops.keys().all(|k| k.starts_with('$')) => ops
+                .iter()
+                .map(|(op, operand)| Self::parse(op, operand))
+                .collect(),
+            _ => Ok(vec![Self::Eq(value.clone())]),
+        }
+    }
+
+    fn parse(op: &str, operand: &Value) -> Result<Self> {
+        let condition = match op {
+            "$eq" => Self::Eq(operand.clone()),
+            "$ne" => Self::Ne(operand.clone()),
+            "$gt" => Self::Gt(operand.clone()),
+            "$gte" => Self::Gte(operand.clone()),
+            "$lt" => Self::Lt(operand.clone()),
+            "$lte" => Self::Lte(operand.clone()),
+            "$in" | "$nin" => {
+                let Value::Array(values) = operand else {
+                    bail!("Operator {} expects an array of values.", op);
+                };
+
+                if op == "$in" {
+                    Self::In(values.clone())
+                } else {
+                    Self::Nin(values.clone())
+                }
+            }
+            "$exists" => {
+                let Value::Bool(exists) = operand else {
+                    bail!("Operator $exists expects a boolean.");
+                };
+
+                Self::Exists(*exists)
+            }
+            "$not" => match operand {
+                Value::Object(ops) if !ops.is_empty() => Self::Not(
+                    ops.iter()
+                        .map(|(op, operand)| Self::parse(op, operand))
+                        .collect::<Result<_>>()?,
+                ),
+                _ => bail!("Operator $not expects an object of operators."),
+            },
+            _ => bail!("Unsupported filter operator: {}", op),
+        };
+
+        Ok(condition)
+    }
+
+    /// Checks the condition against the value found at a path, `None` meaning the path is
+    /// missing. Comparisons against arrays match if any element of the array matches.
+    pub fn matches(&self, value: Option<&Value>) -> bool {
+        match self {
+            Self::Eq(expected) => matches_eq(value, expected),
+            Self::Ne(expected) => !matches_eq(value, expected),
+            Self::Gt(bound) => matches_cmp(value, bound, |o| o == Ordering::Greater),
+            Self::Gte(bound) => matches_cmp(value, bound, |o| o != Ordering::Less),
+            Self::Lt(bound) => matches_cmp(value, bound, |o| o == Ordering::Less),
+            Self::Lte(bound) => matches_cmp(value, bound, |o| o != Ordering::Greater),
+            Self::In(values) => values.iter().any(|v| matches_eq(value, v)),
+            Self::Nin(values) => !values.iter().any(|v| matches_eq(value, v)),
+            Self::Exists(exists) => value.is_some() == *exists,
+            Self::Not(conditions) => !conditions.iter().all(|c| c.matches(value)),
+        }
+    }
+}
+
+/// Missing fields are considered equal to `null`.
+fn matches_eq(value: Option<&Value>, expected: &Value) -> bool {
+    let Some(value) = value else {
+        return expected.is_null();
+    };
+
+    let equals = |v: &Value| compare_values(v, expected) == Ordering::Equal;
+
+    match value {
+        Value::Array(items) => equals(value) || items.iter().any(equals),
+        _ => equals(value),
+    }
+}
+
+/// Range comparisons only match values of the same type as the bound.
+fn matches_cmp(value: Option<&Value>, bound: &Value, accept: impl Fn(Ordering) -> bool) -> bool {
+    let matches_one =
+        |v: &Value| type_rank(v) == type_rank(bound) && accept(compare_values(v, bound));
+
+    match value {
+        Some(Value::Array(items)) if !bound.is_array() => items.iter().any(matches_one),
+        Some(value) => matches_one(value),
+        None => false,
+    }
+}
+
+/// Position of a value's type in the total ordering used by [`compare_values`].
+pub fn type_rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Number(_) => 1,
+        Value::String(_) => 2,
+        Value::Object(_) => 3,
+        Value::Array(_) => 4,
+        Value::Bool(_) => 5,
+    }
+}
+
+/// Total ordering over JSON values. Values of different types are ordered
+/// `null < numbers < strings < objects < arrays < booleans`; numbers compare numerically
+/// regardless of whether they were written as integers or floats.
+pub fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64()) {
+                return a.cmp(&b);
+            }
+
+            if let (Some(a), Some(b)) = (a.as_u64(), b.as_u64()) {
+                return a.cmp(&b);
+            }
+
+            let (a, b) = (
+                a.as_f64().unwrap_or_default(),
+                b.as_f64().unwrap_or_default(),
+            );
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare_values(a, b))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Value::Object(a), Value::Object(b)) => a
+            .iter()
+            .zip(b)
+            .map(|((ak, av), (bk, bv))| ak.cmp(bk).then_with(|| compare_values(av, bv)))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        _ => type_rank(a).cmp(&type_rank(b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(value: Value) -> Record {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn matches(filter: Value, value: Value) -> bool {
+        Filter::parse(&filter).unwrap().matches(&record(value))
+    }
+
+    #[test]
+    fn matches_equality() {
+        let bob = json!({"name": "bob", "age": 30, "tags": ["a", "b"]});
+
+        assert!(matches(json!({"name": "bob"}), bob.clone()));
+        assert!(matches(json!({"name": "bob", "age": 30.0}), bob.clone()));
+        assert!(matches(json!({"tags": "a"}), bob.clone()));
+        assert!(matches(json!({"tags": ["a", "b"]}), bob.clone()));
+        assert!(matches(json!({"missing": null}), bob.clone()));
+        assert!(matches(json!({"name": {"$eq": "bob"}}), bob.clone()));
+        assert!(!matches(json!({"name": "alice"}), bob.clone()));
+        assert!(!matches(json!({"name": "bob", "age": 31}), bob.clone()));
+        assert!(matches(json!({}), bob));
+    }
+
+    #[test]
+    fn matches_comparisons() {
+        let bob = json!({"age": 30, "name": "bob", "scores": [1, 5]});
+
+        assert!(matches(json!({"age": {"$gt": 29}}), bob.clone()));
+        assert!(!matches(json!({"age": {"$gt": 30}}), bob.clone()));
+        assert!(matches(json!({"age": {"$gte": 30}}), bob.clone()));
+        assert!(matches(json!({"age": {"$lt": 30.5}}), bob.clone()));
+        assert!(!matches(json!({"age": {"$lt": 30}}), bob.clone()));
+        assert!(matches(json!({"age": {"$lte": 30}}), bob.clone()));
+        assert!(matches(json!({"age": {"$gt": 18, "$lt": 65}}), bob.clone()));
+        assert!(matches(json!({"age": {"$ne": 31}}), bob.clone()));
+        assert!(!matches(json!({"age": {"$ne": 30}}), bob.clone()));
+        assert!(matches(json!({"name": {"$gte": "b"}}), bob.clone()));
+        assert!(matches(json!({"scores": {"$gt": 4}}), bob.clone()));
+        assert!(!matches(json!({"scores": {"$gt": 5}}), bob.clone()));
+        // Range comparisons only match values of the bound's type.
+        assert!(!matches(json!({"age": {"$gt": "1"}}), bob.clone()));
+        assert!(!matches(json!({"missing": {"$lt": 1}}), bob));
+    }
+
+    #[test]
+    fn matches_membership() {
+        let bob = json!({"role": "admin", "tags": ["a", "b"]});
+
+        assert!(matches(
+            json!({"role": {"$in": ["admin", "owner"]}}),
+            bob.clone()
+        ));
+        assert!(!matches(json!({"role": {"$in": ["user"]}}), bob.clone()));
+        assert!(matches(json!({"tags": {"$in": ["b", "c"]}}), bob.clone()));
+        assert!(matches(json!({"role": {"$nin": ["user"]}}), bob.clone()));
+        assert!(!matches(json!({"tags": {"$nin": ["b"]}}), bob.clone()));
+        assert!(matches(json!({"missing": {"$in": [null]}}), bob));
+    }
+
+    #[test]
+    fn matches_existence() {
+        let bob = json!({"name": "bob", "email": null});
+
+        assert!(matches(json!({"name": {"$exists": true}}), bob.clone()));
+        assert!(matches(json!({"email": {"$exists": true}}), bob.clone()));
+        assert!(matches(json!({"phone": {"$exists": false}}), bob.clone()));
+        assert!(!matches(json!({"name": {"$exists": false}}), bob));
+    }
+
+    #[test]
+    fn matches_logical_operators() {
+        let bob = json!({"name": "bob", "age": 30});
+
+        assert!(matches(
+            json!({"$and": [{"name": "bob"}, {"age": {"$gte": 18}}]}),
+            bob.clone()
+        ));
+        assert!(!matches(
+            json!({"$and": [{"name": "bob"}, {"age": {"$lt": 18}}]}),
+            bob.clone()
+        ));
+        assert!(matches(
+            json!({"$or": [{"name": "alice"}, {"age": 30}]}),
+            bob.clone()
+        ));
+        assert!(!matches(
+            json!({"$or": [{"name": "alice"}, {"age": 31}]}),
+            bob.clone()
+        ));
+        assert!(matches(json!({"$not": {"name": "alice"}}), bob.clone()));
+        assert!(!matches(json!({"$not": {"name": "bob"}}), bob.clone()));
+        assert!(matches(json!({"age": {"$not": {"$gt": 40}}}), bob.clone()));
+        assert!(!matches(json!({"age": {"$not": {"$gt": 20}}}), bob));
+    }
+
+    #[test]
+    fn matches_dotted_paths() {
+        let bob = json!({
+            "address": {"city": "Paris", "geo": {"lat": 48.8}},
+            "orders": [{"total": 10}, {"total": 25}],
+        });
+
+        assert!(matches(json!({"address.city": "Paris"}), bob.clone()));
+        assert!(matches(
+            json!({"address.geo.lat": {"$gt": 48}}),
+            bob.clone()
+        ));
+        assert!(matches(json!({"orders.1.total": 25}), bob.clone()));
+        assert!(!matches(
+            json!({"orders.2.total": {"$exists": true}}),
+            bob.clone()
+        ));
+        assert!(!matches(
+            json!({"address.zip": {"$exists": true}}),
+            bob.clone()
+        ));
+        assert!(!matches(json!({"address.city.name": "Paris"}), bob));
+    }
+
+    #[test]
+    fn rejects_invalid_filters() {
+        for filter in [
+            json!({"$nor": [{"a": 1}]}),
+            json!({"a": {"$regex": "x"}}),
+            json!({"a": {"$in": 1}}),
+            json!({"a": {"$exists": 1}}),
+            json!({"a": {"$not": 1}}),
+            json!({"$and": []}),
+            json!({"$or": {"a": 1}}),
+            json!([{"a": 1}]),
+        ] {
+            assert!(Filter::parse(&filter).is_err(), "{}", filter);
+        }
+    }
+
+    #[test]
+    fn orders_values_across_types() {
+        let ordered = [
+            json!(null),
+            json!(-1),
+            json!(1.5),
+            json!(2),
+            json!("a"),
+            json!("b"),
+            json!({"a": 1}),
+            json!([1]),
+            json!([1, 2]),
+            json!(false),
+            json!(true),
+        ];
+
+        for pair in ordered.windows(2) {
+            assert_eq!(compare_values(&pair[0], &pair[1]), Ordering::Less);
+            assert_eq!(compare_values(&pair[1], &pair[0]), Ordering::Greater);
+        }
+
+        assert_eq!(compare_values(&json!(1), &json!(1.0)), Ordering::Equal);
+    }
+
+    fn apply_options(options: Value, records: Value) -> Vec<Record> {
+        let records = serde_json::from_value(records).unwrap();
+
+        QueryOptions::parse(&options).unwrap().apply(records)
+    }
+
+    #[test]
+    fn sorts_by_several_keys() {
+        let records = json!([
+            {"_id": "1", "age": 30, "name": "carol"},
+            {"_id": "2", "age": 25, "name": "bob"},
+            {"_id": "3", "age": 30, "name": "alice"},
+            {"_id": "4", "name": "dave"},
+        ]);
+        let ids = |records: Vec<Record>| -> Vec<Value> {
+            records.into_iter().map(|r| r["_id"].clone()).collect()
+        };
+
+        assert_eq!(
+            ids(apply_options(
+                json!({"sort": ["-age", "name"]}),
+                records.clone()
+            )),
+            vec![json!("3"), json!("1"), json!("2"), json!("4")]
+        );
+        assert_eq!(
+            ids(apply_options(json!({"sort": ["+age", "-name"]}), records)),
+            vec![json!("4"), json!("2"), json!("1"), json!("3")]
+        );
+    }
+
+    #[test]
+    fn projects_fields() {
+        let records = json!([{"_id": "1", "name": "bob", "address": {"city": "Paris", "zip": "75"}, "password": "x"}]);
+
+        assert_eq!(
+            apply_options(
+                json!({"projection": {"name": 1, "address.city": 1}}),
+                records.clone()
+            ),
+            vec![record(
+                json!({"_id": "1", "name": "bob", "address": {"city": "Paris"}})
+            )]
+        );
+        assert_eq!(
+            apply_options(
+                json!({"projection": {"name": true, "_id": 0}}),
+                records.clone()
+            ),
+            vec![record(json!({"name": "bob"}))]
+        );
+        assert_eq!(
+            apply_options(
+                json!({"projection": {"password": 0, "address.zip": false}}),
+                records.clone()
+            ),
+            vec![record(
+                json!({"_id": "1", "name": "bob", "address": {"city": "Paris"}})
+            )]
+        );
+        assert_eq!(
+            apply_options(json!({"projection": {"_id": 0}}), records),
+            vec![record(
+                json!({"name": "bob", "address": {"city": "Paris", "zip": "75"}, "password": "x"})
+            )]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        for options in [
+            json!({"projection": {"name": 1, "password": 0}}),
+            json!({"projection": {"name": 2}}),
+            json!({"projection": {"name": "yes"}}),
+            json!({"skip": -1}),
+            json!({"limit": "10"}),
+            json!({"sort": "name"}),
+            json!({"order": ["name"]}),
+        ] {
+            assert!(QueryOptions::parse(&options).is_err(), "{}", options);
+        }
+    }
+
+    #[test]
+    fn skips_and_limits() {
+        let records = json!([{"n": 1}, {"n": 2}, {"n": 3}, {"n": 4}, {"n": 5}]);
+        let values = |records: Vec<Record>| -> Vec<Value> {
+            records.into_iter().map(|r| r["n"].clone()).collect()
+        };
+
+        assert_eq!(
+            values(apply_options(
+                json!({"skip": 1, "limit": 2}),
+                records.clone()
+            )),
+            vec![json!(2), json!(3)]
+        );
+        assert_eq!(
+            values(apply_options(
+                json!({"sort": ["-n"], "skip": 3}),
+                records.clone()
+            )),
+            vec![json!(2), json!(1)]
+        );
+        assert_eq!(
+            values(apply_options(json!({"limit": 0}), records.clone())),
+            Vec::<Value>::new()
+        );
+        assert!(apply_options(json!({"skip": 10}), records).is_empty());
+    }
+}
@@ -1,39 +1,280 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::mem;
+use std::sync::Arc;
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::fs;
 use uuid::Uuid;
 
-use crate::{constants::MOLECULE_DEFAULT_DATA_COLLECTIONS_PATH, molecule::Molecule};
+use crate::{
+    core::{
+        cache::LoadedCollection,
+        error::CoreError,
+        index::CollectionIndexes,
+        query::{Condition, Filter, QueryOptions},
+        storage::{RecordChange, Storage},
+        update::{Update, UpdateOptions},
+    },
+    molecule::Molecule,
+};
 
 pub type Record = HashMap<String, Value>;
 
+/// Options for bulk inserts, e.g. `{"ordered": false}`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InsertOptions {
+    /// Stop at the first record that fails to insert instead of skipping it and carrying on.
+    #[serde(default = "InsertOptions::default_ordered")]
+    pub ordered: bool,
+}
+
+impl InsertOptions {
+    fn default_ordered() -> bool {
+        true
+    }
+}
+
+impl Default for InsertOptions {
+    fn default() -> Self {
+        Self {
+            ordered: Self::default_ordered(),
+        }
+    }
+}
+
+/// Result of a bulk insert.
+#[derive(Debug, Default, Serialize)]
+pub struct InsertOutcome {
+    /// IDs of the inserted records, in input order.
+    pub inserted_ids: Vec<String>,
+    pub errors: Vec<InsertFailure>,
+}
+
+/// A record of a bulk insert that couldn't be inserted.
+#[derive(Debug, Serialize)]
+pub struct InsertFailure {
+    /// Position of the record in the input.
+    pub index: usize,
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Result of an update applied to every record matching a filter.
+#[derive(Debug, Default, Serialize)]
+pub struct UpdateOutcome {
+    pub matched: usize,
+    pub modified: usize,
+    /// ID of the record inserted by an upsert, if one was.
+    pub upserted_id: Option<String>,
+}
+
+/// Result of deleting every record matching a filter.
+#[derive(Debug, Default, Serialize)]
+pub struct DeleteOutcome {
+    pub deleted: usize,
+}
+
 pub trait MoleculeCoreRecordsApi {
-    async fn delete_record(&self, collection_id: String, record_id: String) -> Result<String>;
+    async fn delete_record(
+        &self,
+        collection_id: String,
+        record_id: String,
+    ) -> Result<Option<String>>;
     async fn create_record(
         &self,
         collection_id: String,
         contents: HashMap<String, Value>,
     ) -> Result<String>;
+    async fn create_records(
+        &self,
+        collection_id: String,
+        contents: Vec<HashMap<String, Value>>,
+        options: InsertOptions,
+    ) -> Result<InsertOutcome>;
+    async fn update_record(
+        &self,
+        collection_id: String,
+        record_id: String,
+        update: Update,
+    ) -> Result<Option<Record>>;
+    async fn replace_record(
+        &self,
+        collection_id: String,
+        record_id: String,
+        contents: HashMap<String, Value>,
+    ) -> Result<Option<Record>>;
+    async fn update_records(
+        &self,
+        collection_id: String,
+        filter: Filter,
+        update: Update,
+        options: UpdateOptions,
+    ) -> Result<UpdateOutcome>;
+    async fn delete_records(&self, collection_id: String, filter: Filter) -> Result<DeleteOutcome>;
     async fn get_record_by_id(
         &self,
         collection_id: String,
         record_id: String,
     ) -> Result<Option<Record>>;
-    async fn get_records(&self, collection_id: String) -> Result<Vec<Record>>;
+    async fn get_records(
+        &self,
+        collection_id: String,
+        options: QueryOptions,
+    ) -> Result<Vec<Record>>;
+    async fn find_records(
+        &self,
+        collection_id: String,
+        filter: Filter,
+        options: QueryOptions,
+    ) -> Result<Vec<Record>>;
+}
+
+/// Loading and persisting a collection's records together with its indexes, so that every write
+/// keeps the two in sync. Loaded collections are cached in memory, so only the first load of a
+/// collection reads it from storage.
+pub trait MoleculeCoreRecordsExt {
+    async fn load_collection(&self, collection_id: &str) -> Result<Arc<LoadedCollection>>;
+    /// Takes a collection out of the cache to be changed by a write, which must persist it or
+    /// restore it unchanged. A write that fails midway drops it, so it's reloaded from storage.
+    async fn take_collection(&self, collection_id: &str) -> Result<LoadedCollection>;
+    fn restore_collection(&self, collection_id: &str, collection: LoadedCollection);
+    async fn persist_records(
+        &self,
+        collection_id: &str,
+        collection: LoadedCollection,
+        changes: Vec<RecordChange>,
+    ) -> Result<()>;
+    async fn persist_indexes(
+        &self,
+        collection_id: &str,
+        collection: LoadedCollection,
+    ) -> Result<()>;
+}
+
+/// Rejects collection IDs that would resolve to a path outside the collection's own files, e.g.
+/// `../map`, before they reach the storage engine.
+fn check_collection_id(collection_id: &str) -> Result<()> {
+    if collection_id.is_empty()
+        || collection_id.contains(['/', '\\'])
+        || collection_id.contains("..")
+    {
+        return Err(CoreError::CollectionNotFound(collection_id.to_string()).into());
+    }
+
+    Ok(())
+}
+
+/// Reports a collection whose files don't exist as not found rather than as a storage failure.
+fn collection_load_error(collection_id: &str, err: anyhow::Error) -> anyhow::Error {
+    match err.downcast_ref::<std::io::Error>() {
+        Some(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => {
+            CoreError::CollectionNotFound(collection_id.to_string()).into()
+        }
+        _ => err,
+    }
+}
+
+impl MoleculeCoreRecordsExt for Molecule {
+    async fn load_collection(&self, collection_id: &str) -> Result<Arc<LoadedCollection>> {
+        if let Some(collection) = self.cache().get(collection_id) {
+            return Ok(collection);
+        }
+
+        check_collection_id(collection_id)?;
+
+        let collection = self
+            .storage
+            .load_collection(collection_id)
+            .await
+            .map_err(|err| collection_load_error(collection_id, err))?;
+        let collection = Arc::new(collection);
+        self.cache()
+            .insert(collection_id.to_string(), collection.clone());
+
+        Ok(collection)
+    }
+
+    async fn take_collection(&self, collection_id: &str) -> Result<LoadedCollection> {
+        let cached = self.cache().take(collection_id);
+
+        match cached {
+            Some(collection) => Ok(Arc::unwrap_or_clone(collection)),
+            None => {
+                check_collection_id(collection_id)?;
+
+                self.storage
+                    .load_collection(collection_id)
+                    .await
+                    .map_err(|err| collection_load_error(collection_id, err))
+            }
+        }
+    }
+
+    fn restore_collection(&self, collection_id: &str, collection: LoadedCollection) {
+        self.cache()
+            .insert(collection_id.to_string(), Arc::new(collection));
+    }
+
+    async fn persist_records(
+        &self,
+        collection_id: &str,
+        mut collection: LoadedCollection,
+        changes: Vec<RecordChange>,
+    ) -> Result<()> {
+        collection.size = self
+            .storage
+            .write_records(collection_id, &collection, &changes)
+            .await?;
+        self.restore_collection(collection_id, collection);
+
+        Ok(())
+    }
+
+    async fn persist_indexes(
+        &self,
+        collection_id: &str,
+        collection: LoadedCollection,
+    ) -> Result<()> {
+        self.storage
+            .write_indexes(collection_id, &collection.indexes)
+            .await?;
+        self.restore_collection(collection_id, collection);
+
+        Ok(())
+    }
 }
 
 impl MoleculeCoreRecordsApi for Molecule {
-    async fn get_records(&self, collection_id: String) -> Result<Vec<Record>> {
-        let collection_path = format!(
-            "{}/{}.json",
-            MOLECULE_DEFAULT_DATA_COLLECTIONS_PATH, collection_id
-        );
-        let items = fs::read(collection_path).await?;
-        let records: Vec<Record> = serde_json::from_slice(&items)?;
+    async fn get_records(
+        &self,
+        collection_id: String,
+        options: QueryOptions,
+    ) -> Result<Vec<Record>> {
+        let _guard = self.collection_lock(&collection_id).read_owned().await;
+        let collection = self.load_collection(&collection_id).await?;
+
+        Ok(apply_options(
+            &collection.indexes,
+            collection.records.clone(),
+            &options,
+        ))
+    }
+
+    async fn find_records(
+        &self,
+        collection_id: String,
+        filter: Filter,
+        options: QueryOptions,
+    ) -> Result<Vec<Record>> {
+        let _guard = self.collection_lock(&collection_id).read_owned().await;
+        let collection = self.load_collection(&collection_id).await?;
+        let matched = matching_records(&collection.records, &collection.indexes, &filter)
+            .cloned()
+            .collect();
 
-        Ok(records)
+        Ok(apply_options(&collection.indexes, matched, &options))
     }
 
     async fn get_record_by_id(
@@ -41,15 +282,97 @@ impl MoleculeCoreRecordsApi for Molecule {
         collection_id: String,
         record_id: String,
     ) -> Result<Option<Record>> {
-        let records: Vec<HashMap<String, Value>> = self.get_records(collection_id).await?;
+        let _guard = self.collection_lock(&collection_id).read_owned().await;
+        let collection = self.load_collection(&collection_id).await?;
 
-        Ok(records
-            .into_iter()
-            .filter(|r| {
-                r.get("_id")
-                    .is_some_and(|id| id.as_str() == Some(record_id.as_str()))
-            })
-            .next())
+        Ok(matching_records(
+            &collection.records,
+            &collection.indexes,
+            &id_filter(&record_id),
+        )
+        .next()
+        .cloned())
+    }
+
+    async fn update_record(
+        &self,
+        collection_id: String,
+        record_id: String,
+        update: Update,
+    ) -> Result<Option<Record>> {
+        let _guard = self.lock_collection_for_write(&collection_id).await;
+        let mut collection = self.take_collection(&collection_id).await?;
+        let Some(record) = collection
+            .records
+            .iter_mut()
+            .find(|r| has_id(r, &record_id))
+        else {
+            self.restore_collection(&collection_id, collection);
+            return Ok(None);
+        };
+
+        let previous = record.clone();
+
+        if !update.apply(record)? {
+            self.restore_collection(&collection_id, collection);
+            return Ok(Some(previous));
+        }
+
+        let updated = record.clone();
+        collection.indexes.replace(&previous, &updated)?;
+        self.persist_records(
+            &collection_id,
+            collection,
+            vec![RecordChange::Put(updated.clone())],
+        )
+        .await?;
+
+        log::info!("Updated record with ID: {}", record_id);
+        Ok(Some(updated))
+    }
+
+    async fn replace_record(
+        &self,
+        collection_id: String,
+        record_id: String,
+        contents: HashMap<String, Value>,
+    ) -> Result<Option<Record>> {
+        let _guard = self.lock_collection_for_write(&collection_id).await;
+        if contents
+            .get("_id")
+            .is_some_and(|id| id.as_str() != Some(record_id.as_str()))
+        {
+            return Err(CoreError::ValidationFailed(
+                "Replacement contents cannot change the record's _id.".to_string(),
+            )
+            .into());
+        }
+
+        let mut collection = self.take_collection(&collection_id).await?;
+        let Some(record) = collection
+            .records
+            .iter_mut()
+            .find(|r| has_id(r, &record_id))
+        else {
+            self.restore_collection(&collection_id, collection);
+            return Ok(None);
+        };
+
+        let mut replacement = contents;
+        replacement.insert("_id".into(), record_id.clone().into());
+
+        collection.indexes.replace(record, &replacement)?;
+        *record = replacement.clone();
+
+        self.persist_records(
+            &collection_id,
+            collection,
+            vec![RecordChange::Put(replacement.clone())],
+        )
+        .await?;
+
+        log::info!("Replaced record with ID: {}", record_id);
+        Ok(Some(replacement))
     }
 
     async fn create_record(
@@ -57,49 +380,782 @@ impl MoleculeCoreRecordsApi for Molecule {
         collection_id: String,
         contents: HashMap<String, Value>,
     ) -> Result<String> {
-        let collection_path = format!(
-            "{}/{}.json",
-            MOLECULE_DEFAULT_DATA_COLLECTIONS_PATH, collection_id
-        );
-        let mut records: Vec<HashMap<String, Value>> = self.get_records(collection_id).await?;
+        let _guard = self.lock_collection_for_write(&collection_id).await;
+        let mut collection = self.take_collection(&collection_id).await?;
         let mut record = HashMap::new();
 
         record.extend(contents);
 
-        if !record.contains_key("_id") {
+        let record_id = assign_record_id(&mut record)?;
+
+        collection.indexes.insert(&record)?;
+        collection.records.push(record.clone());
+
+        log::info!("Created record with ID: {}", record_id);
+        self.persist_records(&collection_id, collection, vec![RecordChange::Put(record)])
+            .await?;
+
+        Ok(record_id)
+    }
+
+    async fn update_records(
+        &self,
+        collection_id: String,
+        filter: Filter,
+        update: Update,
+        options: UpdateOptions,
+    ) -> Result<UpdateOutcome> {
+        let _guard = self.lock_collection_for_write(&collection_id).await;
+        let mut collection = self.take_collection(&collection_id).await?;
+        let candidates = collection.indexes.candidates(&filter);
+        let mut outcome = UpdateOutcome::default();
+        let mut changes = Vec::new();
+
+        for record in collection
+            .records
+            .iter_mut()
+            .filter(|r| is_candidate(r, candidates.as_ref()) && filter.matches(r))
+        {
+            outcome.matched += 1;
+
+            let previous = record.clone();
+
+            if update.apply(record)? {
+                outcome.modified += 1;
+                collection.indexes.replace(&previous, record)?;
+                changes.push(RecordChange::Put(record.clone()));
+            }
+        }
+
+        if outcome.matched == 0 && options.upsert {
+            let mut record = filter.equality_seed();
+            update.apply(&mut record)?;
+
+            let record_id = assign_record_id(&mut record)?;
+            collection.indexes.insert(&record)?;
+            collection.records.push(record.clone());
+            changes.push(RecordChange::Put(record));
+
+            log::info!("Upserted record with ID: {}", record_id);
+            outcome.upserted_id = Some(record_id);
+        }
+
+        if changes.is_empty() {
+            self.restore_collection(&collection_id, collection);
+        } else {
+            self.persist_records(&collection_id, collection, changes)
+                .await?;
+        }
+
+        log::info!(
+            "Updated {} of {} matched record(s).",
+            outcome.modified,
+            outcome.matched
+        );
+        Ok(outcome)
+    }
+
+    async fn delete_records(&self, collection_id: String, filter: Filter) -> Result<DeleteOutcome> {
+        let _guard = self.lock_collection_for_write(&collection_id).await;
+        let mut collection = self.take_collection(&collection_id).await?;
+        let candidates = collection.indexes.candidates(&filter);
+        let (deleted, kept): (Vec<_>, Vec<_>) = mem::take(&mut collection.records)
+            .into_iter()
+            .partition(|r| is_candidate(r, candidates.as_ref()) && filter.matches(r));
+
+        collection.records = kept;
+
+        if deleted.is_empty() {
+            self.restore_collection(&collection_id, collection);
+        } else {
+            deleted.iter().for_each(|r| collection.indexes.remove(r));
+            self.persist_records(&collection_id, collection, deletions(&deleted))
+                .await?;
+        }
+
+        log::info!("Deleted {} record(s).", deleted.len());
+        Ok(DeleteOutcome {
+            deleted: deleted.len(),
+        })
+    }
+
+    async fn create_records(
+        &self,
+        collection_id: String,
+        contents: Vec<HashMap<String, Value>>,
+        options: InsertOptions,
+    ) -> Result<InsertOutcome> {
+        let _guard = self.lock_collection_for_write(&collection_id).await;
+        let mut collection = self.take_collection(&collection_id).await?;
+        let mut outcome = InsertOutcome::default();
+        let mut changes = Vec::new();
+
+        for (index, mut record) in contents.into_iter().enumerate() {
+            match assign_record_id(&mut record).and_then(|record_id| {
+                collection.indexes.insert(&record)?;
+                Ok(record_id)
+            }) {
+                Ok(record_id) => {
+                    collection.records.push(record.clone());
+                    changes.push(RecordChange::Put(record));
+                    outcome.inserted_ids.push(record_id);
+                }
+                Err(err) => {
+                    outcome.errors.push(InsertFailure {
+                        index,
+                        code: err.code(),
+                        message: err.to_string(),
+                    });
+
+                    if options.ordered {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if changes.is_empty() {
+            self.restore_collection(&collection_id, collection);
+        } else {
+            self.persist_records(&collection_id, collection, changes)
+                .await?;
+        }
+
+        log::info!(
+            "Created {} record(s), {} failed.",
+            outcome.inserted_ids.len(),
+            outcome.errors.len()
+        );
+        Ok(outcome)
+    }
+
+    /// Deletes a record by its ID, returning the ID or `None` if there's no record with it.
+    async fn delete_record(
+        &self,
+        collection_id: String,
+        record_id: String,
+    ) -> Result<Option<String>> {
+        let _guard = self.lock_collection_for_write(&collection_id).await;
+        let mut collection = self.take_collection(&collection_id).await?;
+        let (deleted, kept): (Vec<_>, Vec<_>) = mem::take(&mut collection.records)
+            .into_iter()
+            .partition(|r| has_id(r, &record_id));
+
+        collection.records = kept;
+
+        if deleted.is_empty() {
+            self.restore_collection(&collection_id, collection);
+            return Ok(None);
+        }
+
+        deleted.iter().for_each(|r| collection.indexes.remove(r));
+        self.persist_records(&collection_id, collection, deletions(&deleted))
+            .await?;
+
+        log::info!("Deleted record with ID: {}", record_id);
+        Ok(Some(record_id))
+    }
+}
+
+/// Sorts using an index when one covers the sort keys, falling back to sorting in memory.
+fn apply_options(
+    indexes: &CollectionIndexes,
+    mut records: Vec<Record>,
+    options: &QueryOptions,
+) -> Vec<Record> {
+    if indexes.sort(&mut records, &options.sort) {
+        return options.paginate(records);
+    }
+
+    options.apply(records)
+}
+
+/// Records matching `filter`, only checking the ones the collection's indexes can't rule out.
+fn matching_records<'a>(
+    records: &'a [Record],
+    indexes: &CollectionIndexes,
+    filter: &'a Filter,
+) -> impl Iterator<Item = &'a Record> + 'a {
+    let candidates = indexes.candidates(filter);
+
+    records
+        .iter()
+        .filter(move |r| is_candidate(r, candidates.as_ref()) && filter.matches(r))
+}
+
+fn is_candidate(record: &Record, candidates: Option<&BTreeSet<String>>) -> bool {
+    candidates.is_none_or(|ids| {
+        record
+            .get("_id")
+            .and_then(Value::as_str)
+            .is_some_and(|id| ids.contains(id))
+    })
+}
+
+fn id_filter(record_id: &str) -> Filter {
+    Filter::Field("_id".into(), vec![Condition::Eq(record_id.into())])
+}
+
+/// Generates an `_id` for records that don't bring their own and validates the ones that do,
+/// returning the record's ID. Uniqueness is enforced by the collection's `_id` index.
+fn assign_record_id(record: &mut Record) -> Result<String, CoreError> {
+    match record.get("_id") {
+        Some(Value::String(record_id)) if !record_id.is_empty() => Ok(record_id.clone()),
+        Some(_) => Err(CoreError::InvalidId),
+        None => {
             let gen_record_id = Uuid::new_v4().to_string();
             record.insert("_id".into(), gen_record_id.clone().into());
+
+            Ok(gen_record_id)
         }
+    }
+}
 
-        let record_id = &record
-            .get("_id".into())
-            .unwrap_or_default()
-            .as_str()
-            .unwrap_or_default()
-            .to_owned();
+fn deletions(deleted: &[Record]) -> Vec<RecordChange> {
+    deleted
+        .iter()
+        .filter_map(|r| Some(RecordChange::Delete(r.get("_id")?.as_str()?.to_string())))
+        .collect()
+}
 
-        records.push(record);
+fn has_id(record: &Record, record_id: &str) -> bool {
+    record
+        .get("_id")
+        .is_some_and(|id| id.as_str() == Some(record_id))
+}
 
-        log::info!("Created record with ID: {}", record_id);
-        fs::write(collection_path, serde_json::to_vec(&records)?).await?;
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
 
-        Ok(record_id.to_string())
+    use super::*;
+    use crate::core::collection::MoleculeCoreCollectionApi;
+
+    fn contents(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
     }
 
-    async fn delete_record(&self, collection_id: String, record_id: String) -> Result<String> {
-        let collection_path = format!(
-            "{}/{}.json",
-            MOLECULE_DEFAULT_DATA_COLLECTIONS_PATH, collection_id
+    fn core_error(err: anyhow::Error) -> CoreError {
+        CoreError::from(&err)
+    }
+
+    #[tokio::test]
+    async fn keeps_caller_id() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+
+        let record_id = molecule
+            .create_record(collection_id.clone(), contents(json!({"_id": "alice"})))
+            .await
+            .unwrap();
+        let record = molecule
+            .get_record_by_id(collection_id, "alice".into())
+            .await
+            .unwrap();
+
+        assert_eq!(record_id, "alice");
+        assert_eq!(record.unwrap()["_id"], json!("alice"));
+    }
+
+    #[tokio::test]
+    async fn rejects_duplicate_id() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+
+        molecule
+            .create_record(collection_id.clone(), contents(json!({"_id": "alice"})))
+            .await
+            .unwrap();
+        let err = molecule
+            .create_record(
+                collection_id.clone(),
+                contents(json!({"_id": "alice", "n": 1})),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(core_error(err).code(), "duplicate_key");
+        assert_eq!(
+            molecule
+                .get_records(collection_id, QueryOptions::default())
+                .await
+                .unwrap(),
+            vec![contents(json!({"_id": "alice"}))]
         );
-        let records = self.get_records(collection_id).await?;
-        let updated_records = records
-            .iter()
-            .map(|r| r.get("_id").unwrap_or_default().as_str() != Some(&record_id))
-            .collect::<Vec<_>>();
+    }
 
-        fs::write(collection_path, serde_json::to_vec(&updated_records)?).await?;
+    #[tokio::test]
+    async fn rejects_invalid_ids() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
 
-        log::info!("Deleted record with ID: {}", record_id);
-        Ok(record_id)
+        for id in [
+            json!(""),
+            json!(1),
+            json!(null),
+            json!(["a"]),
+            json!({"a": 1}),
+        ] {
+            let err = molecule
+                .create_record(collection_id.clone(), contents(json!({"_id": id})))
+                .await
+                .unwrap_err();
+
+            assert_eq!(core_error(err), CoreError::InvalidId);
+        }
+
+        assert!(
+            molecule
+                .get_records(collection_id, QueryOptions::default())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn generates_missing_id() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+
+        let first = molecule
+            .create_record(collection_id.clone(), contents(json!({"n": 1})))
+            .await
+            .unwrap();
+        let second = molecule
+            .create_record(collection_id.clone(), contents(json!({"n": 1})))
+            .await
+            .unwrap();
+        let record = molecule
+            .get_record_by_id(collection_id, first.clone())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(!first.is_empty());
+        assert_ne!(first, second);
+        assert_eq!(record["_id"], json!(first));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_writes_are_not_lost() {
+        const TASKS: usize = 8;
+        const WRITES: usize = 25;
+
+        let molecule = Arc::new(Molecule::in_memory());
+        let collection_id = molecule.create_collection("counters".into()).await.unwrap();
+
+        molecule
+            .create_record(
+                collection_id.clone(),
+                contents(json!({"_id": "total", "n": 0})),
+            )
+            .await
+            .unwrap();
+
+        let tasks: Vec<_> = (0..TASKS)
+            .map(|task| {
+                let molecule = Arc::clone(&molecule);
+                let collection_id = collection_id.clone();
+
+                tokio::spawn(async move {
+                    let record_id = format!("task-{}", task);
+
+                    molecule
+                        .create_record(
+                            collection_id.clone(),
+                            contents(json!({"_id": record_id, "n": 0})),
+                        )
+                        .await
+                        .unwrap();
+
+                    for _ in 0..WRITES {
+                        let increment = Update::parse(&json!({"$inc": {"n": 1}})).unwrap();
+
+                        molecule
+                            .update_record(collection_id.clone(), "total".into(), increment.clone())
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        molecule
+                            .update_record(collection_id.clone(), record_id.clone(), increment)
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        molecule
+                            .create_record(collection_id.clone(), contents(json!({"task": task})))
+                            .await
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let records = molecule
+            .get_records(collection_id.clone(), QueryOptions::default())
+            .await
+            .unwrap();
+        let total = molecule
+            .get_record_by_id(collection_id.clone(), "total".into())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(records.len(), 1 + TASKS + TASKS * WRITES);
+        assert_eq!(total["n"], json!(TASKS * WRITES));
+
+        for task in 0..TASKS {
+            let record = molecule
+                .get_record_by_id(collection_id.clone(), format!("task-{}", task))
+                .await
+                .unwrap()
+                .unwrap();
+            let created = records
+                .iter()
+                .filter(|r| r.get("task") == Some(&json!(task)))
+                .count();
+
+            assert_eq!(record["n"], json!(WRITES));
+            assert_eq!(created, WRITES);
+        }
+    }
+
+    #[tokio::test]
+    async fn deleting_missing_record_returns_none() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+
+        molecule
+            .create_record(collection_id.clone(), contents(json!({"_id": "alice"})))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            molecule
+                .delete_record(collection_id.clone(), "bob".into())
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            molecule
+                .get_records(collection_id.clone(), QueryOptions::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let err = molecule
+            .delete_record("missing".into(), "alice".into())
+            .await
+            .unwrap_err();
+
+        assert_eq!(core_error(err).code(), "collection_not_found");
+    }
+
+    #[tokio::test]
+    async fn deleting_record_keeps_others() {
+        let molecule = Molecule::in_memory();
+        let users = molecule.create_collection("users".into()).await.unwrap();
+        let posts = molecule.create_collection("posts".into()).await.unwrap();
+
+        for collection_id in [&users, &posts] {
+            for id in ["alice", "bob"] {
+                molecule
+                    .create_record(collection_id.clone(), contents(json!({"_id": id})))
+                    .await
+                    .unwrap();
+            }
+        }
+
+        assert_eq!(
+            molecule
+                .delete_record(users.clone(), "alice".into())
+                .await
+                .unwrap(),
+            Some("alice".into())
+        );
+        assert_eq!(
+            molecule
+                .get_records(users, QueryOptions::default())
+                .await
+                .unwrap(),
+            vec![contents(json!({"_id": "bob"}))]
+        );
+        assert_eq!(
+            molecule
+                .get_records(posts, QueryOptions::default())
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_collection_ids_outside_data_dir() {
+        use crate::core::data_dir::DataDir;
+        use crate::core::storage::StorageEngine;
+
+        for engine in [StorageEngine::Json, StorageEngine::Segment] {
+            let data_dir = DataDir::temp();
+            let molecule = Molecule::on_disk(engine, data_dir.clone()).await;
+            molecule.create_collection("users".into()).await.unwrap();
+
+            for collection_id in ["../map", "..\\map", "a/b", ".."] {
+                let err = molecule
+                    .create_record(collection_id.into(), contents(json!({"n": 1})))
+                    .await
+                    .unwrap_err();
+
+                assert_eq!(core_error(err).code(), "collection_not_found");
+            }
+
+            assert_eq!(molecule.list_collections().await.unwrap().len(), 1);
+            tokio::fs::remove_dir_all(data_dir.root()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn replace_keeps_id() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+
+        molecule
+            .create_record(
+                collection_id.clone(),
+                contents(json!({"_id": "alice", "name": "alice", "age": 30})),
+            )
+            .await
+            .unwrap();
+        molecule
+            .replace_record(
+                collection_id.clone(),
+                "alice".into(),
+                contents(json!({"name": "Alice"})),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        molecule
+            .replace_record(
+                collection_id.clone(),
+                "alice".into(),
+                contents(json!({"_id": "alice", "name": "Alice", "admin": true})),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let err = molecule
+            .replace_record(
+                collection_id.clone(),
+                "alice".into(),
+                contents(json!({"_id": "bob"})),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(core_error(err).code(), "validation_failed");
+        assert_eq!(
+            molecule
+                .replace_record(collection_id.clone(), "bob".into(), contents(json!({})))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            molecule
+                .get_records(collection_id, QueryOptions::default())
+                .await
+                .unwrap(),
+            vec![contents(
+                json!({"_id": "alice", "name": "Alice", "admin": true})
+            )]
+        );
+    }
+
+    fn filter(value: Value) -> Filter {
+        Filter::parse(&value).unwrap()
+    }
+
+    fn update(value: Value) -> Update {
+        Update::parse(&value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn counts_matched_and_modified_records() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+        let records = vec![
+            contents(json!({"_id": "1", "role": "user", "active": true})),
+            contents(json!({"_id": "2", "role": "user"})),
+            contents(json!({"_id": "3", "role": "user"})),
+            contents(json!({"_id": "4", "role": "admin"})),
+        ];
+
+        molecule
+            .create_records(collection_id.clone(), records, InsertOptions::default())
+            .await
+            .unwrap();
+
+        let outcome = molecule
+            .update_records(
+                collection_id.clone(),
+                filter(json!({"role": "user"})),
+                update(json!({"$set": {"active": true}})),
+                UpdateOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            (outcome.matched, outcome.modified, outcome.upserted_id),
+            (3, 2, None)
+        );
+
+        let outcome = molecule
+            .update_records(
+                collection_id.clone(),
+                filter(json!({"role": "owner"})),
+                update(json!({"$set": {"active": true}})),
+                UpdateOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            (outcome.matched, outcome.modified, outcome.upserted_id),
+            (0, 0, None)
+        );
+        assert_eq!(
+            molecule
+                .find_records(
+                    collection_id,
+                    filter(json!({"active": true})),
+                    QueryOptions::default()
+                )
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn upsert_seeds_record_from_filter() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+        let upsert = UpdateOptions { upsert: true };
+        let seed_filter = json!({
+            "email": "bob@example.com",
+            "profile.name": "bob",
+            "age": {"$gt": 18},
+            "$or": [{"role": "admin"}, {"role": "owner"}],
+        });
+
+        let outcome = molecule
+            .update_records(
+                collection_id.clone(),
+                filter(seed_filter.clone()),
+                update(json!({"$inc": {"logins": 1}})),
+                upsert.clone(),
+            )
+            .await
+            .unwrap();
+        let upserted_id = outcome.upserted_id.unwrap();
+
+        assert_eq!((outcome.matched, outcome.modified), (0, 0));
+        assert_eq!(
+            molecule
+                .get_record_by_id(collection_id.clone(), upserted_id.clone())
+                .await
+                .unwrap()
+                .unwrap(),
+            contents(json!({
+                "_id": upserted_id,
+                "email": "bob@example.com",
+                "profile": {"name": "bob"},
+                "logins": 1,
+            }))
+        );
+
+        let outcome = molecule
+            .update_records(
+                collection_id.clone(),
+                filter(json!({"_id": "alice"})),
+                update(json!({"$set": {"name": "alice"}})),
+                upsert.clone(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.upserted_id.as_deref(), Some("alice"));
+
+        let outcome = molecule
+            .update_records(
+                collection_id.clone(),
+                filter(json!({"email": "bob@example.com"})),
+                update(json!({"$inc": {"logins": 1}})),
+                upsert,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            (outcome.matched, outcome.modified, outcome.upserted_id),
+            (1, 1, None)
+        );
+        assert_eq!(
+            molecule
+                .get_records(collection_id, QueryOptions::default())
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_deleted_records() {
+        let molecule = Molecule::in_memory();
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+        let records = (1..=5)
+            .map(|n| contents(json!({"_id": n.to_string(), "n": n})))
+            .collect();
+
+        molecule
+            .create_records(collection_id.clone(), records, InsertOptions::default())
+            .await
+            .unwrap();
+
+        let outcome = molecule
+            .delete_records(collection_id.clone(), filter(json!({"n": {"$gte": 4}})))
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.deleted, 2);
+
+        let outcome = molecule
+            .delete_records(collection_id.clone(), filter(json!({"n": {"$gte": 4}})))
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.deleted, 0);
+        assert_eq!(
+            molecule
+                .get_records(collection_id, QueryOptions::default())
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|r| r["n"].clone())
+                .collect::<Vec<_>>(),
+            vec![json!(1), json!(2), json!(3)]
+        );
     }
 }
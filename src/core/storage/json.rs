@@ -0,0 +1,217 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::{
+    constants::{
+        MOLECULE_DATA_COLLECTION_META_PATH, MOLECULE_DATA_COLLECTIONS_PATH,
+        MOLECULE_DATA_INDEXES_PATH, MOLECULE_DATA_LOST_FOUND_PATH,
+    },
+    core::{
+        cache::LoadedCollection,
+        collection::Collection,
+        consistency::Inconsistency,
+        data_dir::DataDir,
+        index::{CollectionIndexes, IndexDefinition},
+        record::Record,
+        storage::{
+            RecordChange, Storage, collection_files, index_file, index_path, read_collection_map,
+            read_index_definitions,
+        },
+        wal::{Wal, WalOp},
+    },
+};
+
+/// Stores every collection as a JSON array of its records, rewritten in full through the
+/// write-ahead log on every write, with its indexes next to it.
+#[derive(Debug)]
+pub struct JsonStorage {
+    pub data_dir: DataDir,
+    wal: Mutex<Wal>,
+}
+
+impl JsonStorage {
+    pub async fn open(data_dir: DataDir, wal: Wal) -> Result<Self> {
+        fs::create_dir_all(data_dir.path(MOLECULE_DATA_COLLECTIONS_PATH)).await?;
+        fs::create_dir_all(data_dir.path(MOLECULE_DATA_INDEXES_PATH)).await?;
+
+        Ok(Self {
+            data_dir,
+            wal: Mutex::new(wal),
+        })
+    }
+
+    fn collection_path(&self, collection_id: &str) -> String {
+        self.data_dir.path(&collection_file(collection_id))
+    }
+}
+
+/// Path of the file the records of a collection are stored in, relative to the data directory.
+fn collection_file(collection_id: &str) -> String {
+    format!("{}/{}.json", MOLECULE_DATA_COLLECTIONS_PATH, collection_id)
+}
+
+impl Storage for JsonStorage {
+    async fn read_collection_map(&self) -> Result<Vec<Collection>> {
+        read_collection_map(&self.data_dir).await
+    }
+
+    async fn create_collection(
+        &self,
+        collections: &[Collection],
+        collection_id: &str,
+    ) -> Result<()> {
+        self.wal
+            .lock()
+            .await
+            .commit(vec![
+                WalOp::Write {
+                    path: collection_file(collection_id),
+                    contents: "[]".into(),
+                },
+                WalOp::write(MOLECULE_DATA_COLLECTION_META_PATH.into(), collections)?,
+            ])
+            .await
+    }
+
+    async fn delete_collection(
+        &self,
+        collections: &[Collection],
+        collection_id: &str,
+    ) -> Result<()> {
+        self.wal
+            .lock()
+            .await
+            .commit(vec![
+                WalOp::write(MOLECULE_DATA_COLLECTION_META_PATH.into(), collections)?,
+                WalOp::Remove {
+                    path: collection_file(collection_id),
+                },
+                WalOp::Remove {
+                    path: index_file(collection_id),
+                },
+            ])
+            .await
+    }
+
+    /// Reads a collection's records and indexes, building the default `_id` index for
+    /// collections that have never persisted any.
+    async fn load_collection(&self, collection_id: &str) -> Result<LoadedCollection> {
+        let items = fs::read(self.collection_path(collection_id)).await?;
+        let records: Vec<Record> = serde_json::from_slice(&items)?;
+        let index_path = index_path(&self.data_dir, collection_id);
+
+        let indexes = if fs::try_exists(&index_path).await? {
+            serde_json::from_slice(&fs::read(index_path).await?)?
+        } else {
+            CollectionIndexes::new(&records)?
+        };
+
+        Ok(LoadedCollection {
+            records,
+            indexes,
+            size: items.len(),
+        })
+    }
+
+    async fn write_records(
+        &self,
+        collection_id: &str,
+        collection: &LoadedCollection,
+        _changes: &[RecordChange],
+    ) -> Result<usize> {
+        let contents = serde_json::to_string(&collection.records)?;
+        let size = contents.len();
+
+        self.wal
+            .lock()
+            .await
+            .commit(vec![
+                WalOp::Write {
+                    path: collection_file(collection_id),
+                    contents,
+                },
+                WalOp::write(index_file(collection_id), &collection.indexes)?,
+            ])
+            .await?;
+
+        Ok(size)
+    }
+
+    async fn write_indexes(&self, collection_id: &str, indexes: &CollectionIndexes) -> Result<()> {
+        self.wal
+            .lock()
+            .await
+            .commit(vec![WalOp::write(index_file(collection_id), indexes)?])
+            .await
+    }
+
+    async fn index_definitions(&self, collection_id: &str) -> Result<Vec<IndexDefinition>> {
+        read_index_definitions(&self.data_dir, collection_id).await
+    }
+
+    async fn stored_collections(&self) -> Result<HashSet<String>> {
+        collection_files(&self.data_dir.path(MOLECULE_DATA_COLLECTIONS_PATH)).await
+    }
+
+    async fn indexed_collections(&self) -> Result<HashSet<String>> {
+        collection_files(&self.data_dir.path(MOLECULE_DATA_INDEXES_PATH)).await
+    }
+
+    async fn repair(&self, inconsistency: &Inconsistency) -> Result<()> {
+        match inconsistency {
+            Inconsistency::MissingCollection(collection_id) => {
+                self.wal
+                    .lock()
+                    .await
+                    .commit(vec![
+                        WalOp::Write {
+                            path: collection_file(collection_id),
+                            contents: "[]".into(),
+                        },
+                        WalOp::Remove {
+                            path: index_file(collection_id),
+                        },
+                    ])
+                    .await
+            }
+            Inconsistency::OrphanCollection(collection_id) => {
+                let lost_found = self.data_dir.path(MOLECULE_DATA_LOST_FOUND_PATH);
+
+                fs::create_dir_all(&lost_found).await?;
+                fs::rename(
+                    self.collection_path(collection_id),
+                    format!("{}/{}.json", lost_found, collection_id),
+                )
+                .await?;
+
+                Ok(())
+            }
+            Inconsistency::OrphanIndex(collection_id) => {
+                self.wal
+                    .lock()
+                    .await
+                    .commit(vec![WalOp::Remove {
+                        path: index_file(collection_id),
+                    }])
+                    .await
+            }
+        }
+    }
+
+    async fn checkpoint(&self) -> Result<()> {
+        self.wal.lock().await.checkpoint().await
+    }
+
+    /// Collection files are rewritten in full by every write, so there's never anything to
+    /// reclaim.
+    async fn compact(&self, _collection_id: &str) -> Result<u64> {
+        Ok(0)
+    }
+
+    async fn compaction_candidates(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+
+use crate::core::{
+    cache::LoadedCollection,
+    collection::Collection,
+    consistency::Inconsistency,
+    error::CoreError,
+    index::{CollectionIndexes, IndexDefinition},
+    record::Record,
+    storage::{RecordChange, Storage},
+};
+
+/// Keeps the collection map and every collection in memory only, so nothing outlives the
+/// process. Meant for tests and ephemeral caches.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    collection_map: Mutex<Vec<Collection>>,
+    collections: Mutex<HashMap<String, StoredCollection>>,
+}
+
+/// A collection's records and index definitions. Like with the segment engine, writes only
+/// apply the records they changed and the indexes are rebuilt when the collection is loaded, so
+/// a write never copies the whole collection.
+#[derive(Debug)]
+struct StoredCollection {
+    /// Records by the order they were first inserted in.
+    records: BTreeMap<u64, Record>,
+    /// Ordinal and size of every record, by ID.
+    ids: HashMap<String, (u64, usize)>,
+    next_ordinal: u64,
+    definitions: Vec<IndexDefinition>,
+    /// Size of the JSON of every record.
+    size: usize,
+}
+
+impl StoredCollection {
+    fn new() -> Self {
+        Self {
+            records: BTreeMap::new(),
+            ids: HashMap::new(),
+            next_ordinal: 0,
+            definitions: vec![IndexDefinition::id()],
+            size: 0,
+        }
+    }
+
+    fn apply(&mut self, change: &RecordChange) -> Result<()> {
+        match change {
+            RecordChange::Put(record) => {
+                let Some(record_id) = record.get("_id").and_then(|id| id.as_str()) else {
+                    return Ok(());
+                };
+                let size = serde_json::to_vec(record)?.len();
+                let ordinal = match self.ids.get(record_id) {
+                    Some(&(ordinal, previous)) => {
+                        self.size -= previous;
+                        ordinal
+                    }
+                    None => {
+                        self.next_ordinal += 1;
+                        self.next_ordinal
+                    }
+                };
+
+                self.size += size;
+                self.ids.insert(record_id.to_string(), (ordinal, size));
+                self.records.insert(ordinal, record.clone());
+            }
+            RecordChange::Delete(record_id) => {
+                if let Some((ordinal, size)) = self.ids.remove(record_id) {
+                    self.size -= size;
+                    self.records.remove(&ordinal);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Storage for MemoryStorage {
+    async fn read_collection_map(&self) -> Result<Vec<Collection>> {
+        Ok(self.collection_map.lock().await.clone())
+    }
+
+    async fn create_collection(
+        &self,
+        collections: &[Collection],
+        collection_id: &str,
+    ) -> Result<()> {
+        self.collections
+            .lock()
+            .await
+            .insert(collection_id.to_string(), StoredCollection::new());
+        *self.collection_map.lock().await = collections.to_vec();
+
+        Ok(())
+    }
+
+    async fn delete_collection(
+        &self,
+        collections: &[Collection],
+        collection_id: &str,
+    ) -> Result<()> {
+        *self.collection_map.lock().await = collections.to_vec();
+        self.collections.lock().await.remove(collection_id);
+
+        Ok(())
+    }
+
+    async fn load_collection(&self, collection_id: &str) -> Result<LoadedCollection> {
+        let collections = self.collections.lock().await;
+        let Some(collection) = collections.get(collection_id) else {
+            return Err(CoreError::CollectionNotFound(collection_id.to_string()).into());
+        };
+        let records: Vec<Record> = collection.records.values().cloned().collect();
+
+        Ok(LoadedCollection {
+            indexes: CollectionIndexes::build(collection.definitions.clone(), &records)?,
+            records,
+            size: collection.size,
+        })
+    }
+
+    /// Applies only the changed records, sizing the collection as the JSON of its records so
+    /// it's cached the same way as with the other engines.
+    async fn write_records(
+        &self,
+        collection_id: &str,
+        _collection: &LoadedCollection,
+        changes: &[RecordChange],
+    ) -> Result<usize> {
+        let mut collections = self.collections.lock().await;
+        let Some(collection) = collections.get_mut(collection_id) else {
+            return Err(CoreError::CollectionNotFound(collection_id.to_string()).into());
+        };
+
+        for change in changes {
+            collection.apply(change)?;
+        }
+
+        Ok(collection.size)
+    }
+
+    async fn write_indexes(&self, collection_id: &str, indexes: &CollectionIndexes) -> Result<()> {
+        if let Some(collection) = self.collections.lock().await.get_mut(collection_id) {
+            collection.definitions = indexes.definitions();
+        }
+
+        Ok(())
+    }
+
+    async fn index_definitions(&self, collection_id: &str) -> Result<Vec<IndexDefinition>> {
+        Ok(self
+            .collections
+            .lock()
+            .await
+            .get(collection_id)
+            .map(|collection| collection.definitions.clone())
+            .unwrap_or_default())
+    }
+
+    async fn stored_collections(&self) -> Result<HashSet<String>> {
+        Ok(self.collections.lock().await.keys().cloned().collect())
+    }
+
+    async fn indexed_collections(&self) -> Result<HashSet<String>> {
+        self.stored_collections().await
+    }
+
+    /// Collections and the collection map are always changed together, so they can't disagree.
+    async fn repair(&self, _inconsistency: &Inconsistency) -> Result<()> {
+        Ok(())
+    }
+
+    async fn checkpoint(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn compact(&self, _collection_id: &str) -> Result<u64> {
+        Ok(0)
+    }
+
+    async fn compaction_candidates(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn record(value: Value) -> Record {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn applies_changes_in_place() {
+        let storage = MemoryStorage::default();
+        let collection = LoadedCollection {
+            records: Vec::new(),
+            indexes: CollectionIndexes::new(&[]).unwrap(),
+            size: 0,
+        };
+
+        storage.create_collection(&[], "users").await.unwrap();
+
+        let changes = [
+            RecordChange::Put(record(json!({"_id": "a", "n": 1}))),
+            RecordChange::Put(record(json!({"_id": "b", "n": 1}))),
+            RecordChange::Put(record(json!({"_id": "a", "n": 22}))),
+            RecordChange::Delete("b".into()),
+            RecordChange::Put(record(json!({"_id": "c", "n": 3}))),
+        ];
+        let size = storage
+            .write_records("users", &collection, &changes)
+            .await
+            .unwrap();
+        let loaded = storage.load_collection("users").await.unwrap();
+        let records = vec![
+            record(json!({"_id": "a", "n": 22})),
+            record(json!({"_id": "c", "n": 3})),
+        ];
+
+        assert_eq!(loaded.records, records);
+        assert_eq!(loaded.size, size);
+        assert_eq!(
+            size,
+            records
+                .iter()
+                .map(|r| serde_json::to_vec(r).unwrap().len())
+                .sum::<usize>()
+        );
+        assert_eq!(loaded.indexes.definitions(), vec![IndexDefinition::id()]);
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashSet;
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use serde::Deserialize;
+use tokio::fs;
+
+use crate::constants::{MOLECULE_DATA_COLLECTION_META_PATH, MOLECULE_DATA_INDEXES_PATH};
+use crate::core::{
+    atomic::TEMP_FILE_SUFFIX,
+    cache::LoadedCollection,
+    collection::Collection,
+    consistency::Inconsistency,
+    data_dir::DataDir,
+    index::{CollectionIndexes, IndexDefinition},
+    record::Record,
+    wal::Wal,
+};
+
+pub mod json;
+pub mod memory;
+pub mod segment;
+
+use json::JsonStorage;
+use memory::MemoryStorage;
+use segment::SegmentStorage;
+
+/// A change to a single record, made by a write.
+#[derive(Debug, Clone)]
+pub enum RecordChange {
+    /// The record was inserted or replaced.
+    Put(Record),
+    /// The record with this ID was deleted.
+    Delete(String),
+}
+
+/// Persistence of the collection map and of every collection's records and indexes.
+pub trait Storage {
+    async fn read_collection_map(&self) -> Result<Vec<Collection>>;
+    /// Stores `collections` as the collection map, along with an empty collection for
+    /// `collection_id`.
+    async fn create_collection(
+        &self,
+        collections: &[Collection],
+        collection_id: &str,
+    ) -> Result<()>;
+    /// Stores `collections` as the collection map, removing everything stored for
+    /// `collection_id`.
+    async fn delete_collection(
+        &self,
+        collections: &[Collection],
+        collection_id: &str,
+    ) -> Result<()>;
+    async fn load_collection(&self, collection_id: &str) -> Result<LoadedCollection>;
+    /// Persists the changes made to a collection's records, along with the indexes updated for
+    /// them, returning the collection's new size in storage.
+    async fn write_records(
+        &self,
+        collection_id: &str,
+        collection: &LoadedCollection,
+        changes: &[RecordChange],
+    ) -> Result<usize>;
+    async fn write_indexes(&self, collection_id: &str, indexes: &CollectionIndexes) -> Result<()>;
+    /// Definitions of a collection's stored indexes, read without loading its records.
+    async fn index_definitions(&self, collection_id: &str) -> Result<Vec<IndexDefinition>>;
+    /// IDs of the collections with records in storage.
+    async fn stored_collections(&self) -> Result<HashSet<String>>;
+    /// IDs of the collections with indexes in storage.
+    async fn indexed_collections(&self) -> Result<HashSet<String>>;
+    async fn repair(&self, inconsistency: &Inconsistency) -> Result<()>;
+    /// Makes every write so far durable without the write-ahead log.
+    async fn checkpoint(&self) -> Result<()>;
+    /// Reclaims the space taken by deleted and superseded records of a collection, returning
+    /// how many bytes were reclaimed. Writes to the collection must be held off until it
+    /// returns, reads needn't be.
+    async fn compact(&self, collection_id: &str) -> Result<u64>;
+    /// IDs of the collections with enough space to reclaim to be worth compacting.
+    async fn compaction_candidates(&self) -> Vec<String>;
+}
+
+/// On-disk format the records of collections are stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageEngine {
+    /// Log-structured segments only ever appended to, so writes cost as much as the records
+    /// they change.
+    Segment,
+    /// One JSON file per collection, rewritten in full by every write. Simple to inspect, fine
+    /// for small deployments.
+    Json,
+}
+
+impl StorageEngine {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Segment => "segment",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self> {
+        match <Self as ValueEnum>::from_str(name.trim(), true) {
+            Ok(engine) => Ok(engine),
+            Err(_) => bail!("Unknown storage engine: {}", name.trim()),
+        }
+    }
+}
+
+/// The storage engine a database was opened with.
+#[derive(Debug)]
+pub enum StorageBackend {
+    Segment(SegmentStorage),
+    Json(JsonStorage),
+    /// Started with `--in-memory`, keeping nothing once the database stops.
+    Memory(MemoryStorage),
+}
+
+impl StorageBackend {
+    pub async fn open(engine: StorageEngine, data_dir: DataDir, wal: Wal) -> Result<Self> {
+        Ok(match engine {
+            StorageEngine::Segment => Self::Segment(SegmentStorage::open(data_dir, wal).await?),
+            StorageEngine::Json => Self::Json(JsonStorage::open(data_dir, wal).await?),
+        })
+    }
+
+    /// Directory the database persists to, or `None` when it runs in memory.
+    pub fn data_dir(&self) -> Option<&DataDir> {
+        match self {
+            Self::Segment(storage) => Some(&storage.data_dir),
+            Self::Json(storage) => Some(&storage.data_dir),
+            Self::Memory(_) => None,
+        }
+    }
+}
+
+impl Storage for StorageBackend {
+    async fn read_collection_map(&self) -> Result<Vec<Collection>> {
+        match self {
+            Self::Segment(storage) => storage.read_collection_map().await,
+            Self::Json(storage) => storage.read_collection_map().await,
+            Self::Memory(storage) => storage.read_collection_map().await,
+        }
+    }
+
+    async fn create_collection(
+        &self,
+        collections: &[Collection],
+        collection_id: &str,
+    ) -> Result<()> {
+        match self {
+            Self::Segment(storage) => storage.create_collection(collections, collection_id).await,
+            Self::Json(storage) => storage.create_collection(collections, collection_id).await,
+            Self::Memory(storage) => storage.create_collection(collections, collection_id).await,
+        }
+    }
+
+    async fn delete_collection(
+        &self,
+        collections: &[Collection],
+        collection_id: &str,
+    ) -> Result<()> {
+        match self {
+            Self::Segment(storage) => storage.delete_collection(collections, collection_id).await,
+            Self::Json(storage) => storage.delete_collection(collections, collection_id).await,
+            Self::Memory(storage) => storage.delete_collection(collections, collection_id).await,
+        }
+    }
+
+    async fn load_collection(&self, collection_id: &str) -> Result<LoadedCollection> {
+        match self {
+            Self::Segment(storage) => storage.load_collection(collection_id).await,
+            Self::Json(storage) => storage.load_collection(collection_id).await,
+            Self::Memory(storage) => storage.load_collection(collection_id).await,
+        }
+    }
+
+    async fn write_records(
+        &self,
+        collection_id: &str,
+        collection: &LoadedCollection,
+        changes: &[RecordChange],
+    ) -> Result<usize> {
+        match self {
+            Self::Segment(storage) => {
+                storage
+                    .write_records(collection_id, collection, changes)
+                    .await
+            }
+            Self::Json(storage) => {
+                storage
+                    .write_records(collection_id, collection, changes)
+                    .await
+            }
+            Self::Memory(storage) => {
+                storage
+                    .write_records(collection_id, collection, changes)
+                    .await
+            }
+        }
+    }
+
+    async fn write_indexes(&self, collection_id: &str, indexes: &CollectionIndexes) -> Result<()> {
+        match self {
+            Self::Segment(storage) => storage.write_indexes(collection_id, indexes).await,
+            Self::Json(storage) => storage.write_indexes(collection_id, indexes).await,
+            Self::Memory(storage) => storage.write_indexes(collection_id, indexes).await,
+        }
+    }
+
+    async fn index_definitions(&self, collection_id: &str) -> Result<Vec<IndexDefinition>> {
+        match self {
+            Self::Segment(storage) => storage.index_definitions(collection_id).await,
+            Self::Json(storage) => storage.index_definitions(collection_id).await,
+            Self::Memory(storage) => storage.index_definitions(collection_id).await,
+        }
+    }
+
+    async fn stored_collections(&self) -> Result<HashSet<String>> {
+        match self {
+            Self::Segment(storage) => storage.stored_collections().await,
+            Self::Json(storage) => storage.stored_collections().await,
+            Self::Memory(storage) => storage.stored_collections().await,
+        }
+    }
+
+    async fn indexed_collections(&self) -> Result<HashSet<String>> {
+        match self {
+            Self::Segment(storage) => storage.indexed_collections().await,
+            Self::Json(storage) => storage.indexed_collections().await,
+            Self::Memory(storage) => storage.indexed_collections().await,
+        }
+    }
+
+    async fn repair(&self, inconsistency: &Inconsistency) -> Result<()> {
+        match self {
+            Self::Segment(storage) => storage.repair(inconsistency).await,
+            Self::Json(storage) => storage.repair(inconsistency).await,
+            Self::Memory(storage) => storage.repair(inconsistency).await,
+        }
+    }
+
+    async fn checkpoint(&self) -> Result<()> {
+        match self {
+            Self::Segment(storage) => storage.checkpoint().await,
+            Self::Json(storage) => storage.checkpoint().await,
+            Self::Memory(storage) => storage.checkpoint().await,
+        }
+    }
+
+    async fn compact(&self, collection_id: &str) -> Result<u64> {
+        match self {
+            Self::Segment(storage) => storage.compact(collection_id).await,
+            Self::Json(storage) => storage.compact(collection_id).await,
+            Self::Memory(storage) => storage.compact(collection_id).await,
+        }
+    }
+
+    async fn compaction_candidates(&self) -> Vec<String> {
+        match self {
+            Self::Segment(storage) => storage.compaction_candidates().await,
+            Self::Json(storage) => storage.compaction_candidates().await,
+            Self::Memory(storage) => storage.compaction_candidates().await,
+        }
+    }
+}
+
+pub async fn read_collection_map(data_dir: &DataDir) -> Result<Vec<Collection>> {
+    let collection_meta_content =
+        fs::read(data_dir.path(MOLECULE_DATA_COLLECTION_META_PATH)).await?;
+    let parsed_meta: Vec<Collection> = serde_json::from_slice(&collection_meta_content)?;
+
+    Ok(parsed_meta)
+}
+
+/// Path of the file the indexes of a collection are persisted to, relative to the data
+/// directory.
+pub fn index_file(collection_id: &str) -> String {
+    format!("{}/{}.json", MOLECULE_DATA_INDEXES_PATH, collection_id)
+}
+
+/// Path of the file the indexes of a collection are persisted to.
+pub fn index_path(data_dir: &DataDir, collection_id: &str) -> String {
+    data_dir.path(&index_file(collection_id))
+}
+
+/// Definitions of the indexes persisted for a collection, none if it has no index file. Index
+/// files holding whole indexes are read for their definitions only.
+pub async fn read_index_definitions(
+    data_dir: &DataDir,
+    collection_id: &str,
+) -> Result<Vec<IndexDefinition>> {
+    let index_path = index_path(data_dir, collection_id);
+
+    if !fs::try_exists(&index_path).await? {
+        return Ok(Vec::new());
+    }
+
+    Ok(serde_json::from_slice(&fs::read(index_path).await?)?)
+}
+
+/// IDs of the collections with a `.json` file in `dir`, removing temporary files left behind by
+/// writes interrupted before they replaced their file.
+pub async fn collection_files(dir: &str) -> Result<HashSet<String>> {
+    let mut entries = fs::read_dir(dir).await?;
+    let mut collection_ids = HashSet::new();
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if name.ends_with(TEMP_FILE_SUFFIX) {
+            log::warn!("Removing interrupted write: {}", path.display());
+            fs::remove_file(&path).await?;
+            continue;
+        }
+
+        if let Some(collection_id) = name.strip_suffix(".json")
+            && entry.file_type().await?.is_file()
+        {
+            collection_ids.insert(collection_id.to_string());
+        }
+    }
+
+    Ok(collection_ids)
+}
@@ -0,0 +1,917 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::{
+    constants::{
+        MOLECULE_DATA_COLLECTION_META_PATH, MOLECULE_DATA_INDEXES_PATH,
+        MOLECULE_DATA_LOST_FOUND_PATH, MOLECULE_DATA_SEGMENTS_PATH,
+        MOLECULE_MIN_COMPACTION_GARBAGE, MOLECULE_SEGMENT_SIZE,
+    },
+    core::{
+        atomic::TEMP_FILE_SUFFIX,
+        cache::LoadedCollection,
+        collection::Collection,
+        consistency::Inconsistency,
+        data_dir::DataDir,
+        index::{CollectionIndexes, IndexDefinition},
+        record::Record,
+        storage::{
+            RecordChange, Storage, collection_files, index_file, index_path, read_collection_map,
+            read_index_definitions,
+        },
+        wal::{Wal, WalOp, crc32},
+    },
+};
+
+/// Every entry is a big-endian `u32` payload length and CRC-32 of the flags and payload,
+/// followed by a flags byte and the payload: a record as JSON, or the ID of a deleted record.
+const SEGMENT_ENTRY_HEADER_LEN: usize = 9;
+/// The entry deletes the record with the ID in its payload.
+const ENTRY_TOMBSTONE: u8 = 0b01;
+/// The entry is the last of a write, which is only applied once all of its entries are stored.
+const ENTRY_END_OF_WRITE: u8 = 0b10;
+
+/// Where the latest version of a record is stored.
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    segment: u64,
+    offset: usize,
+    len: usize,
+    /// Position of the record in its collection, kept across updates.
+    ordinal: u64,
+}
+
+/// The segments of a single collection along with its key directory, locating the latest
+/// version of every live record.
+#[derive(Debug)]
+struct SegmentLog {
+    /// Directory the segments are stored in.
+    dir: String,
+    keydir: HashMap<String, Location>,
+    next_ordinal: u64,
+    /// Segment being appended to, always the latest one.
+    active: u64,
+    file: File,
+    active_size: u64,
+    /// Bytes taken by live records, as opposed to deleted or superseded ones.
+    live_bytes: usize,
+    /// Bytes taken by every segment, live or not.
+    total_bytes: u64,
+}
+
+/// Live records of a collection rewritten into fresh segments by a compaction, not yet swapped
+/// in for the segments they were read from.
+struct CompactedSegments {
+    /// Numbers of the new segments, oldest first. There's always at least one, so there's a
+    /// segment to append to afterwards.
+    segments: Vec<u64>,
+    keydir: HashMap<String, Location>,
+    /// Size of the last new segment.
+    active_size: u64,
+    total_bytes: u64,
+}
+
+/// An entry decoded from a segment.
+struct SegmentEntry<'a> {
+    offset: usize,
+    len: usize,
+    flags: u8,
+    payload: &'a [u8],
+}
+
+/// Stores collections as log-structured segments: every write appends the records it changed to
+/// the collection's latest segment, so writes cost as much as the records they change no matter
+/// how large the collection is. The collection map and index definitions go through the
+/// write-ahead log, and indexes are rebuilt from the records on load.
+#[derive(Debug)]
+pub struct SegmentStorage {
+    pub data_dir: DataDir,
+    wal: Mutex<Wal>,
+    /// Logs of the collections opened so far, each locked on its own so compacting one
+    /// collection never holds up the others.
+    logs: Mutex<HashMap<String, Arc<Mutex<SegmentLog>>>>,
+    /// Held for the whole of a compaction, so only one runs at a time.
+    compaction_lock: Mutex<()>,
+}
+
+impl SegmentStorage {
+    pub async fn open(data_dir: DataDir, wal: Wal) -> Result<Self> {
+        fs::create_dir_all(data_dir.path(MOLECULE_DATA_SEGMENTS_PATH)).await?;
+        fs::create_dir_all(data_dir.path(MOLECULE_DATA_INDEXES_PATH)).await?;
+
+        Ok(Self {
+            data_dir,
+            wal: Mutex::new(wal),
+            logs: Mutex::new(HashMap::new()),
+            compaction_lock: Mutex::new(()),
+        })
+    }
+
+    /// The log of a collection, opening its segments on first use. The records replayed while
+    /// opening are returned along with it.
+    async fn log(
+        &self,
+        collection_id: &str,
+    ) -> Result<(Arc<Mutex<SegmentLog>>, Option<Vec<Record>>)> {
+        let mut logs = self.logs.lock().await;
+
+        if let Some(log) = logs.get(collection_id) {
+            return Ok((log.clone(), None));
+        }
+
+        let (log, records) = SegmentLog::open(self.segments_dir(collection_id)).await?;
+        let log = Arc::new(Mutex::new(log));
+
+        logs.insert(collection_id.to_string(), log.clone());
+        Ok((log, Some(records)))
+    }
+
+    fn segments_dir(&self, collection_id: &str) -> String {
+        format!(
+            "{}/{}",
+            self.data_dir.path(MOLECULE_DATA_SEGMENTS_PATH),
+            collection_id
+        )
+    }
+}
+
+fn segment_path(dir: &str, segment: u64) -> String {
+    format!("{}/{:010}.seg", dir, segment)
+}
+
+/// Numbers of a collection's segments, oldest first. Segments left behind by a compaction
+/// interrupted before swapping them in are removed.
+async fn list_segments(dir: &str) -> Result<Vec<u64>> {
+    let mut entries = fs::read_dir(dir).await?;
+    let mut segments = Vec::new();
+
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if name.ends_with(TEMP_FILE_SUFFIX) {
+            log::warn!(
+                "Removing interrupted compaction: {}",
+                entry.path().display()
+            );
+            fs::remove_file(entry.path()).await?;
+            continue;
+        }
+
+        if let Some(segment) = name.strip_suffix(".seg").and_then(|n| n.parse().ok()) {
+            segments.push(segment);
+        }
+    }
+
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+/// Decodes the entries of every complete write in a segment, returning them along with the
+/// length of the segment they span. Anything after that is a write torn by a crash.
+fn decode_segment(bytes: &[u8]) -> (Vec<SegmentEntry<'_>>, usize) {
+    let mut entries = Vec::new();
+    let mut complete = 0;
+    let mut complete_len = 0;
+    let mut offset = 0;
+
+    while let Some(header) = bytes[offset..].first_chunk::<SEGMENT_ENTRY_HEADER_LEN>() {
+        let payload_len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let checksum = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let len = SEGMENT_ENTRY_HEADER_LEN + payload_len;
+
+        let Some(body) = bytes.get(offset + SEGMENT_ENTRY_HEADER_LEN - 1..offset + len) else {
+            break;
+        };
+
+        if crc32(body) != checksum {
+            break;
+        }
+
+        entries.push(SegmentEntry {
+            offset,
+            len,
+            flags: body[0],
+            payload: &body[1..],
+        });
+        offset += len;
+
+        if body[0] & ENTRY_END_OF_WRITE != 0 {
+            complete = entries.len();
+            complete_len = offset;
+        }
+    }
+
+    entries.truncate(complete);
+    (entries, complete_len)
+}
+
+fn encode_entry(buf: &mut Vec<u8>, flags: u8, payload: &[u8]) {
+    let mut body = Vec::with_capacity(payload.len() + 1);
+    body.push(flags);
+    body.extend_from_slice(payload);
+
+    buf.extend((payload.len() as u32).to_be_bytes());
+    buf.extend(crc32(&body).to_be_bytes());
+    buf.extend(body);
+}
+
+fn compacted_segment_path(dir: &str, segment: u64) -> String {
+    format!("{}{}", segment_path(dir, segment), TEMP_FILE_SUFFIX)
+}
+
+/// Rewrites the live records of `keydir` into temporary segments numbered from `first_segment`
+/// on, in the order they were inserted and each as a write of its own.
+async fn compact_segments(
+    dir: &str,
+    keydir: &HashMap<String, Location>,
+    first_segment: u64,
+) -> Result<CompactedSegments> {
+    let mut by_segment: BTreeMap<u64, Vec<(&String, &Location)>> = BTreeMap::new();
+    let mut payloads = Vec::with_capacity(keydir.len());
+
+    for (record_id, location) in keydir {
+        by_segment
+            .entry(location.segment)
+            .or_default()
+            .push((record_id, location));
+    }
+
+    for (segment, locations) in by_segment {
+        let path = segment_path(dir, segment);
+        let bytes = fs::read(&path).await?;
+
+        for (record_id, location) in locations {
+            let Some(payload) = bytes
+                .get(location.offset + SEGMENT_ENTRY_HEADER_LEN..location.offset + location.len)
+            else {
+                bail!("Segment {} is truncated.", path);
+            };
+
+            payloads.push((location.ordinal, record_id, payload.to_vec()));
+        }
+    }
+
+    payloads.sort_unstable_by_key(|(ordinal, ..)| *ordinal);
+
+    let mut compacted = CompactedSegments {
+        segments: vec![first_segment],
+        keydir: HashMap::with_capacity(keydir.len()),
+        active_size: 0,
+        total_bytes: 0,
+    };
+    let mut buf = Vec::new();
+    let mut payloads = payloads.into_iter().peekable();
+
+    loop {
+        let segment = *compacted.segments.last().unwrap_or(&first_segment);
+
+        while buf.len() < MOLECULE_SEGMENT_SIZE as usize
+            && let Some((ordinal, record_id, payload)) = payloads.next()
+        {
+            let offset = buf.len();
+            encode_entry(&mut buf, ENTRY_END_OF_WRITE, &payload);
+
+            compacted.keydir.insert(
+                record_id.clone(),
+                Location {
+                    segment,
+                    offset,
+                    len: buf.len() - offset,
+                    ordinal,
+                },
+            );
+        }
+
+        let mut file = File::create(compacted_segment_path(dir, segment)).await?;
+        file.write_all(&buf).await?;
+        file.sync_all().await?;
+
+        compacted.active_size = buf.len() as u64;
+        compacted.total_bytes += buf.len() as u64;
+        buf.clear();
+
+        if payloads.peek().is_none() {
+            return Ok(compacted);
+        }
+
+        compacted.segments.push(segment + 1);
+    }
+}
+
+async fn sync_dir(dir: &str) -> Result<()> {
+    File::open(dir).await?.sync_all().await?;
+
+    Ok(())
+}
+
+async fn open_segment(dir: &str, segment: u64) -> Result<File> {
+    Ok(OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(segment_path(dir, segment))
+        .await?)
+}
+
+impl SegmentLog {
+    /// Opens a collection's segments, replaying them into its key directory and records. A
+    /// write torn at the end of the latest segment is discarded.
+    async fn open(dir: String) -> Result<(Self, Vec<Record>)> {
+        let segments = list_segments(&dir).await?;
+        let mut keydir = HashMap::new();
+        let mut records: HashMap<String, Record> = HashMap::new();
+        let mut next_ordinal = 0;
+        let mut active_size = 0;
+        let mut total_bytes = 0;
+
+        for (i, &segment) in segments.iter().enumerate() {
+            let path = segment_path(&dir, segment);
+            let bytes = fs::read(&path).await?;
+            let (entries, len) = decode_segment(&bytes);
+
+            if len < bytes.len() {
+                if i + 1 < segments.len() {
+                    bail!("Segment {} is corrupted.", path);
+                }
+
+                log::warn!(
+                    "Discarded {} bytes of an incomplete write to segment {}.",
+                    bytes.len() - len,
+                    path
+                );
+                OpenOptions::new()
+                    .write(true)
+                    .open(&path)
+                    .await?
+                    .set_len(len as u64)
+                    .await?;
+            }
+
+            for entry in entries {
+                if entry.flags & ENTRY_TOMBSTONE != 0 {
+                    let record_id = String::from_utf8_lossy(entry.payload);
+                    keydir.remove(record_id.as_ref());
+                    records.remove(record_id.as_ref());
+                    continue;
+                }
+
+                let record: Record = serde_json::from_slice(entry.payload)?;
+                let Some(record_id) = record.get("_id").and_then(|id| id.as_str()) else {
+                    bail!("Segment {} holds a record without an _id.", path);
+                };
+                let ordinal = match keydir.get(record_id) {
+                    Some(Location { ordinal, .. }) => *ordinal,
+                    None => {
+                        next_ordinal += 1;
+                        next_ordinal
+                    }
+                };
+
+                keydir.insert(
+                    record_id.to_string(),
+                    Location {
+                        segment,
+                        offset: entry.offset,
+                        len: entry.len,
+                        ordinal,
+                    },
+                );
+                records.insert(record_id.to_string(), record);
+            }
+
+            active_size = len as u64;
+            total_bytes += len as u64;
+        }
+
+        let active = segments.last().copied().unwrap_or(1);
+        let log = Self {
+            live_bytes: keydir.values().map(|l| l.len).sum(),
+            file: open_segment(&dir, active).await?,
+            dir,
+            keydir,
+            next_ordinal,
+            active,
+            active_size,
+            total_bytes,
+        };
+        let records = log.order(records);
+
+        Ok((log, records))
+    }
+
+    /// Whether enough of the segments are taken by deleted or superseded records to be worth
+    /// compacting: at least as much as the live records, and no less than
+    /// `MOLECULE_MIN_COMPACTION_GARBAGE`.
+    fn needs_compaction(&self) -> bool {
+        let garbage = self.total_bytes.saturating_sub(self.live_bytes as u64);
+
+        garbage >= MOLECULE_MIN_COMPACTION_GARBAGE && garbage >= self.live_bytes as u64
+    }
+
+    /// Reads every live record through the key directory.
+    async fn read(&self) -> Result<Vec<Record>> {
+        let mut by_segment: BTreeMap<u64, Vec<(&String, &Location)>> = BTreeMap::new();
+        let mut records = HashMap::new();
+
+        for (record_id, location) in &self.keydir {
+            by_segment
+                .entry(location.segment)
+                .or_default()
+                .push((record_id, location));
+        }
+
+        for (segment, locations) in by_segment {
+            let path = segment_path(&self.dir, segment);
+            let bytes = fs::read(&path).await?;
+
+            for (record_id, location) in locations {
+                let Some(payload) = bytes.get(
+                    location.offset + SEGMENT_ENTRY_HEADER_LEN..location.offset + location.len,
+                ) else {
+                    bail!("Segment {} is truncated.", path);
+                };
+
+                records.insert(record_id.clone(), serde_json::from_slice(payload)?);
+            }
+        }
+
+        Ok(self.order(records))
+    }
+
+    /// Orders records the way they were first inserted.
+    fn order(&self, mut records: HashMap<String, Record>) -> Vec<Record> {
+        let mut ordered: Vec<_> = self.keydir.iter().collect();
+        ordered.sort_unstable_by_key(|(_, l)| l.ordinal);
+
+        ordered
+            .into_iter()
+            .filter_map(|(record_id, _)| records.remove(record_id))
+            .collect()
+    }
+
+    /// Durably appends the changes of a single write, starting a new segment first if the
+    /// active one is full.
+    async fn append(&mut self, changes: &[RecordChange]) -> Result<()> {
+        if self.active_size >= MOLECULE_SEGMENT_SIZE {
+            self.file.sync_all().await?;
+            self.active += 1;
+            self.active_size = 0;
+            self.file = open_segment(&self.dir, self.active).await?;
+            sync_dir(&self.dir).await?;
+        }
+
+        let mut buf = Vec::new();
+        let mut locations = Vec::with_capacity(changes.len());
+
+        for (i, change) in changes.iter().enumerate() {
+            let end = if i + 1 == changes.len() {
+                ENTRY_END_OF_WRITE
+            } else {
+                0
+            };
+            let offset = self.active_size as usize + buf.len();
+
+            match change {
+                RecordChange::Put(record) => {
+                    encode_entry(&mut buf, end, &serde_json::to_vec(record)?);
+                }
+                RecordChange::Delete(record_id) => {
+                    encode_entry(&mut buf, ENTRY_TOMBSTONE | end, record_id.as_bytes());
+                }
+            }
+
+            locations.push((offset, self.active_size as usize + buf.len() - offset));
+        }
+
+        let written = async {
+            self.file.write_all(&buf).await?;
+            self.file.sync_data().await
+        }
+        .await;
+
+        if let Err(err) = written {
+            // Cut off whatever part of the write reached the segment, so the next write isn't
+            // appended after a torn one.
+            self.file.set_len(self.active_size).await?;
+            return Err(err.into());
+        }
+
+        self.active_size += buf.len() as u64;
+        self.total_bytes += buf.len() as u64;
+
+        for (change, (offset, len)) in changes.iter().zip(locations) {
+            match change {
+                RecordChange::Put(record) => {
+                    let Some(record_id) = record.get("_id").and_then(|id| id.as_str()) else {
+                        continue;
+                    };
+                    let ordinal = match self.keydir.get(record_id) {
+                        Some(previous) => {
+                            self.live_bytes -= previous.len;
+                            previous.ordinal
+                        }
+                        None => {
+                            self.next_ordinal += 1;
+                            self.next_ordinal
+                        }
+                    };
+
+                    self.live_bytes += len;
+                    self.keydir.insert(
+                        record_id.to_string(),
+                        Location {
+                            segment: self.active,
+                            offset,
+                            len,
+                            ordinal,
+                        },
+                    );
+                }
+                RecordChange::Delete(record_id) => {
+                    if let Some(previous) = self.keydir.remove(record_id) {
+                        self.live_bytes -= previous.len;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Storage for SegmentStorage {
+    async fn read_collection_map(&self) -> Result<Vec<Collection>> {
+        read_collection_map(&self.data_dir).await
+    }
+
+    async fn create_collection(
+        &self,
+        collections: &[Collection],
+        collection_id: &str,
+    ) -> Result<()> {
+        fs::create_dir_all(self.segments_dir(collection_id)).await?;
+
+        self.wal
+            .lock()
+            .await
+            .commit(vec![WalOp::write(
+                MOLECULE_DATA_COLLECTION_META_PATH.into(),
+                collections,
+            )?])
+            .await
+    }
+
+    async fn delete_collection(
+        &self,
+        collections: &[Collection],
+        collection_id: &str,
+    ) -> Result<()> {
+        self.wal
+            .lock()
+            .await
+            .commit(vec![
+                WalOp::write(MOLECULE_DATA_COLLECTION_META_PATH.into(), collections)?,
+                WalOp::Remove {
+                    path: index_file(collection_id),
+                },
+            ])
+            .await?;
+
+        self.logs.lock().await.remove(collection_id);
+
+        match fs::remove_dir_all(self.segments_dir(collection_id)).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads a collection's records through its key directory, opening its segments on first
+    /// use, and rebuilds its indexes from their persisted definitions.
+    async fn load_collection(&self, collection_id: &str) -> Result<LoadedCollection> {
+        let (log, opened) = self.log(collection_id).await?;
+        let log = log.lock().await;
+
+        let (records, size) = match opened {
+            Some(records) => (records, log.live_bytes),
+            None => (log.read().await?, log.live_bytes),
+        };
+
+        drop(log);
+
+        let index_path = index_path(&self.data_dir, collection_id);
+        let indexes = if fs::try_exists(&index_path).await? {
+            let definitions: Vec<IndexDefinition> =
+                serde_json::from_slice(&fs::read(index_path).await?)?;
+
+            CollectionIndexes::build(definitions, &records)?
+        } else {
+            CollectionIndexes::new(&records)?
+        };
+
+        Ok(LoadedCollection {
+            records,
+            indexes,
+            size,
+        })
+    }
+
+    async fn write_records(
+        &self,
+        collection_id: &str,
+        _collection: &LoadedCollection,
+        changes: &[RecordChange],
+    ) -> Result<usize> {
+        let (log, _) = self.log(collection_id).await?;
+        let mut log = log.lock().await;
+
+        if !changes.is_empty()
+            && let Err(err) = log.append(changes).await
+        {
+            drop(log);
+            // Reopening the segments on next use discards anything the failed write left behind.
+            self.logs.lock().await.remove(collection_id);
+            return Err(err);
+        }
+
+        Ok(log.live_bytes)
+    }
+
+    async fn write_indexes(&self, collection_id: &str, indexes: &CollectionIndexes) -> Result<()> {
+        self.wal
+            .lock()
+            .await
+            .commit(vec![WalOp::write(
+                index_file(collection_id),
+                &indexes.definitions(),
+            )?])
+            .await
+    }
+
+    async fn index_definitions(&self, collection_id: &str) -> Result<Vec<IndexDefinition>> {
+        read_index_definitions(&self.data_dir, collection_id).await
+    }
+
+    async fn stored_collections(&self) -> Result<HashSet<String>> {
+        let mut entries = fs::read_dir(self.data_dir.path(MOLECULE_DATA_SEGMENTS_PATH)).await?;
+        let mut collection_ids = HashSet::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                collection_ids.insert(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+
+        Ok(collection_ids)
+    }
+
+    async fn indexed_collections(&self) -> Result<HashSet<String>> {
+        collection_files(&self.data_dir.path(MOLECULE_DATA_INDEXES_PATH)).await
+    }
+
+    async fn repair(&self, inconsistency: &Inconsistency) -> Result<()> {
+        match inconsistency {
+            Inconsistency::MissingCollection(collection_id) => {
+                fs::create_dir_all(self.segments_dir(collection_id)).await?;
+
+                self.wal
+                    .lock()
+                    .await
+                    .commit(vec![WalOp::Remove {
+                        path: index_file(collection_id),
+                    }])
+                    .await
+            }
+            Inconsistency::OrphanCollection(collection_id) => {
+                let lost_found = self.data_dir.path(MOLECULE_DATA_LOST_FOUND_PATH);
+
+                fs::create_dir_all(&lost_found).await?;
+                fs::rename(
+                    self.segments_dir(collection_id),
+                    format!("{}/{}", lost_found, collection_id),
+                )
+                .await?;
+
+                Ok(())
+            }
+            Inconsistency::OrphanIndex(collection_id) => {
+                self.wal
+                    .lock()
+                    .await
+                    .commit(vec![WalOp::Remove {
+                        path: index_file(collection_id),
+                    }])
+                    .await
+            }
+        }
+    }
+
+    async fn checkpoint(&self) -> Result<()> {
+        self.wal.lock().await.checkpoint().await
+    }
+
+    /// Rewrites the live records into temporary segments numbered after the active one, then
+    /// swaps them in: they're renamed into place, the key directory is pointed at them and the
+    /// old segments are removed, oldest first. The log is only locked to snapshot the key
+    /// directory and for the swap, so loads of the collection go on while records are copied.
+    /// Replaying the new segments after any of the old ones yields the same records, so a crash
+    /// at any point of the swap loses nothing.
+    async fn compact(&self, collection_id: &str) -> Result<u64> {
+        let _guard = self.compaction_lock.lock().await;
+        let (log, _) = self.log(collection_id).await?;
+
+        let dir = self.segments_dir(collection_id);
+
+        let (keydir, first_segment, old_segments, old_bytes) = {
+            let log = log.lock().await;
+
+            (
+                log.keydir.clone(),
+                log.active + 1,
+                list_segments(&dir).await?,
+                log.total_bytes,
+            )
+        };
+
+        let compacted = compact_segments(&dir, &keydir, first_segment).await?;
+
+        for &segment in &compacted.segments {
+            fs::rename(
+                compacted_segment_path(&dir, segment),
+                segment_path(&dir, segment),
+            )
+            .await?;
+        }
+
+        sync_dir(&dir).await?;
+
+        let mut log = log.lock().await;
+        let active = *compacted.segments.last().unwrap_or(&first_segment);
+
+        log.file = open_segment(&dir, active).await?;
+        log.active = active;
+        log.active_size = compacted.active_size;
+        log.total_bytes = compacted.total_bytes;
+        log.keydir = compacted.keydir;
+
+        for segment in old_segments {
+            fs::remove_file(segment_path(&dir, segment)).await?;
+        }
+
+        sync_dir(&dir).await?;
+
+        Ok(old_bytes.saturating_sub(compacted.total_bytes))
+    }
+
+    async fn compaction_candidates(&self) -> Vec<String> {
+        let logs: Vec<_> = self
+            .logs
+            .lock()
+            .await
+            .iter()
+            .map(|(collection_id, log)| (collection_id.clone(), log.clone()))
+            .collect();
+        let mut candidates = Vec::new();
+
+        for (collection_id, log) in logs {
+            if log.lock().await.needs_compaction() {
+                candidates.push(collection_id);
+            }
+        }
+
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::compaction::MoleculeCompactionApi;
+    use crate::core::collection::MoleculeCoreCollectionApi;
+    use crate::core::query::QueryOptions;
+    use crate::core::record::MoleculeCoreRecordsApi;
+    use crate::core::storage::StorageEngine;
+    use crate::core::update::Update;
+    use crate::molecule::Molecule;
+
+    fn contents(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    /// Every entry of a collection's segments, as whether it's a tombstone and its payload,
+    /// along with the segments' total size.
+    async fn segment_entries(dir: &str) -> (Vec<(bool, Vec<u8>)>, u64) {
+        let mut entries = Vec::new();
+        let mut size = 0;
+
+        for segment in list_segments(dir).await.unwrap() {
+            let bytes = fs::read(segment_path(dir, segment)).await.unwrap();
+            let (decoded, len) = decode_segment(&bytes);
+
+            assert_eq!(len, bytes.len());
+            size += len as u64;
+            entries.extend(
+                decoded
+                    .into_iter()
+                    .map(|e| (e.flags & ENTRY_TOMBSTONE != 0, e.payload.to_vec())),
+            );
+        }
+
+        (entries, size)
+    }
+
+    #[tokio::test]
+    async fn compaction_keeps_only_live_records() {
+        let data_dir = DataDir::temp();
+        let molecule = Molecule::on_disk(StorageEngine::Segment, data_dir.clone()).await;
+        let collection_id = molecule.create_collection("users".into()).await.unwrap();
+        let dir = format!(
+            "{}/{}",
+            data_dir.path(MOLECULE_DATA_SEGMENTS_PATH),
+            collection_id
+        );
+
+        for id in ["a", "b", "c", "d"] {
+            molecule
+                .create_record(collection_id.clone(), contents(json!({"_id": id, "n": 0})))
+                .await
+                .unwrap();
+        }
+
+        for _ in 0..3 {
+            molecule
+                .update_record(
+                    collection_id.clone(),
+                    "a".into(),
+                    Update::parse(&json!({"$inc": {"n": 1}})).unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        for id in ["b", "c"] {
+            molecule
+                .delete_record(collection_id.clone(), id.into())
+                .await
+                .unwrap();
+        }
+
+        let (entries, size_before) = segment_entries(&dir).await;
+        assert_eq!(entries.len(), 4 + 3 + 2);
+
+        let reclaimed = molecule
+            .compact_collection(collection_id.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        let (entries, size_after) = segment_entries(&dir).await;
+        let expected = vec![
+            contents(json!({"_id": "a", "n": 3})),
+            contents(json!({"_id": "d", "n": 0})),
+        ];
+
+        assert!(reclaimed > 0);
+        assert_eq!(reclaimed, size_before - size_after);
+        assert!(entries.iter().all(|(tombstone, _)| !tombstone));
+        assert_eq!(
+            entries
+                .iter()
+                .map(|(_, payload)| serde_json::from_slice::<Record>(payload).unwrap())
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(
+            molecule
+                .get_records(collection_id.clone(), QueryOptions::default())
+                .await
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            molecule.compact_collection("missing".into()).await.unwrap(),
+            None
+        );
+
+        drop(molecule);
+
+        let molecule = Molecule::on_disk(StorageEngine::Segment, data_dir.clone()).await;
+
+        assert_eq!(
+            molecule
+                .get_records(collection_id, QueryOptions::default())
+                .await
+                .unwrap(),
+            expected
+        );
+
+        fs::remove_dir_all(data_dir.root()).await.unwrap();
+    }
+}
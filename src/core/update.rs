@@ -0,0 +1,418 @@
+use std::cmp::Ordering;
+
+use anyhow::{Result, bail};
+use serde::Deserialize;
+use serde_json::{Number, Value};
+
+use crate::core::error::CoreError;
+use crate::core::path::{lookup_path, remove_path, set_path};
+use crate::core::query::{Condition, compare_values};
+use crate::core::record::Record;
+
+/// A parsed update document, e.g. `{"$set": {"name": "bob"}, "$inc": {"logins": 1}}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Update(Vec<UpdateOp>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateOp {
+    Set(String, Value),
+    Unset(String),
+    Inc(String, Number),
+    Mul(String, Number),
+    /// Appends values to an array, creating it if missing.
+    Push(String, Vec<Value>),
+    /// Removes every array element matching the conditions.
+    Pull(String, Vec<Condition>),
+    /// Appends values to an array unless an equal element is already present.
+    AddToSet(String, Vec<Value>),
+    Rename(String, String),
+}
+
+/// Options for multi-record updates, e.g. `{"upsert": true}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateOptions {
+    /// Insert a new record built from the filter's equality conditions and the update when no
+    /// record matches the filter.
+    #[serde(default)]
+    pub upsert: bool,
+}
+
+impl Update {
+    pub fn parse(value: &Value) -> Result<Self> {
+        let Value::Object(operators) = value else {
+            bail!("Update must be a JSON object.");
+        };
+
+        if operators.is_empty() {
+            bail!("Update must contain at least one operator.");
+        }
+
+        let mut ops = Vec::new();
+
+        for (operator, fields) in operators {
+            let Value::Object(fields) = fields else {
+                bail!("Operator {} expects an object of fields.", operator);
+            };
+
+            for (path, operand) in fields {
+                ensure_not_id(path)?;
+                ops.push(UpdateOp::parse(operator, path, operand)?);
+            }
+        }
+
+        Ok(Self(ops))
+    }
+
+    /// Applies every operator in order, returning whether the record changed. The record is left
+    /// untouched if any operator fails.
+    pub fn apply(&self, record: &mut Record) -> Result<bool> {
+        let mut updated = record.clone();
+
+        for op in &self.0 {
+            op.apply(&mut updated)
+                .map_err(|err| CoreError::ValidationFailed(err.to_string()))?;
+        }
+
+        if updated == *record {
+            return Ok(false);
+        }
+
+        *record = updated;
+        Ok(true)
+    }
+}
+
+impl UpdateOp {
+    fn parse(operator: &str, path: &str, operand: &Value) -> Result<Self> {
+        let path = path.to_string();
+        let op = match operator {
+            "$set" => Self::Set(path, operand.clone()),
+            "$unset" => Self::Unset(path),
+            "$inc" | "$mul" => {
+                let Value::Number(n) = operand else {
+                    bail!("Operator {} expects a number for {}.", operator, path);
+                };
+
+                if operator == "$inc" {
+                    Self::Inc(path, n.clone())
+                } else {
+                    Self::Mul(path, n.clone())
+                }
+            }
+            "$push" => Self::Push(path, each_values(operand)),
+            "$addToSet" => Self::AddToSet(path, each_values(operand)),
+            "$pull" => Self::Pull(path, Condition::parse_all(operand)?),
+            "$rename" => {
+                let Value::String(target) = operand else {
+                    bail!("Operator $rename expects a string for {}.", path);
+                };
+
+                ensure_not_id(target)?;
+                Self::Rename(path, target.clone())
+            }
+            _ => bail!("Unsupported update operator: {}", operator),
+        };
+
+        Ok(op)
+    }
+
+    fn apply(&self, record: &mut Record) -> Result<()> {
+        match self {
+            Self::Set(path, value) => set_value(record, path, value.clone())?,
+            Self::Unset(path) => {
+                remove_path(record, path);
+            }
+            Self::Inc(path, amount) => {
+                let value = match lookup_path(record, path) {
+                    None => Value::Number(amount.clone()),
+                    Some(Value::Number(current)) => {
+                        combine_numbers(current, amount, i64::checked_add, |a, b| a + b)?
+                    }
+                    Some(_) => bail!("Cannot apply $inc to non-numeric field {}.", path),
+                };
+
+                set_value(record, path, value)?;
+            }
+            Self::Mul(path, factor) => {
+                let value = match lookup_path(record, path) {
+                    None => Value::from(0),
+                    Some(Value::Number(current)) => {
+                        combine_numbers(current, factor, i64::checked_mul, |a, b| a * b)?
+                    }
+                    Some(_) => bail!("Cannot apply $mul to non-numeric field {}.", path),
+                };
+
+                set_value(record, path, value)?;
+            }
+            Self::Push(path, values) => {
+                let mut items = array_at(record, path)?;
+                items.extend(values.iter().cloned());
+                set_value(record, path, Value::Array(items))?;
+            }
+            Self::AddToSet(path, values) => {
+                let mut items = array_at(record, path)?;
+
+                for value in values {
+                    if !items
+                        .iter()
+                        .any(|item| compare_values(item, value) == Ordering::Equal)
+                    {
+                        items.push(value.clone());
+                    }
+                }
+
+                set_value(record, path, Value::Array(items))?;
+            }
+            Self::Pull(path, conditions) => {
+                if lookup_path(record, path).is_none() {
+                    return Ok(());
+                }
+
+                let mut items = array_at(record, path)?;
+                items.retain(|item| !conditions.iter().all(|c| c.matches(Some(item))));
+                set_value(record, path, Value::Array(items))?;
+            }
+            Self::Rename(from, to) => {
+                if let Some(value) = remove_path(record, from) {
+                    set_value(record, to, value)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Every operator works on the record's contents, its `_id` can't be changed by an update.
+fn ensure_not_id(path: &str) -> Result<()> {
+    if path == "_id" || path.starts_with("_id.") {
+        bail!("Updates cannot modify _id.");
+    }
+
+    Ok(())
+}
+
+/// `$push` and `$addToSet` take either a single value or `{"$each": [...]}`.
+fn each_values(operand: &Value) -> Vec<Value> {
+    match operand {
+        Value::Object(map) if map.len() == 1 => match map.get("$each") {
+            Some(Value::Array(values)) => values.clone(),
+            _ => vec![operand.clone()],
+        },
+        _ => vec![operand.clone()],
+    }
+}
+
+fn set_value(record: &mut Record, path: &str, value: Value) -> Result<()> {
+    if !set_path(record, path, value) {
+        bail!(
+            "Cannot set field {}, a parent of it is not an object.",
+            path
+        );
+    }
+
+    Ok(())
+}
+
+fn array_at(record: &Record, path: &str) -> Result<Vec<Value>> {
+    match lookup_path(record, path) {
+        None => Ok(Vec::new()),
+        Some(Value::Array(items)) => Ok(items.clone()),
+        Some(_) => bail!("Field {} is not an array.", path),
+    }
+}
+
+/// Combines two numbers, staying in integers when both are integers and the result fits.
+fn combine_numbers(
+    a: &Number,
+    b: &Number,
+    int_op: fn(i64, i64) -> Option<i64>,
+    float_op: fn(f64, f64) -> f64,
+) -> Result<Value> {
+    if let Some(result) = a.as_i64().zip(b.as_i64()).and_then(|(a, b)| int_op(a, b)) {
+        return Ok(Value::from(result));
+    }
+
+    let result = float_op(
+        a.as_f64().unwrap_or_default(),
+        b.as_f64().unwrap_or_default(),
+    );
+
+    match Number::from_f64(result) {
+        Some(n) => Ok(Value::Number(n)),
+        None => bail!("Numeric update produced a non-finite result."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(value: Value) -> Record {
+        serde_json::from_value(value).unwrap()
+    }
+
+    /// Applies an update to a record, returning the updated record.
+    fn apply(update: Value, value: Value) -> Result<Record> {
+        let mut record = record(value);
+        Update::parse(&update)?.apply(&mut record)?;
+
+        Ok(record)
+    }
+
+    #[test]
+    fn sets_and_unsets_fields() {
+        assert_eq!(
+            apply(
+                json!({"$set": {"name": "bob", "address.city": "Paris"}}),
+                json!({"_id": "1", "name": "alice"})
+            )
+            .unwrap(),
+            record(json!({"_id": "1", "name": "bob", "address": {"city": "Paris"}}))
+        );
+        assert_eq!(
+            apply(
+                json!({"$unset": {"password": "", "missing": ""}}),
+                json!({"_id": "1", "password": "x"})
+            )
+            .unwrap(),
+            record(json!({"_id": "1"}))
+        );
+        assert!(
+            apply(
+                json!({"$set": {"name.first": "bob"}}),
+                json!({"name": "bob"})
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn increments_and_multiplies() {
+        assert_eq!(
+            apply(
+                json!({"$inc": {"logins": 1, "score": 0.5, "new": 2}}),
+                json!({"logins": 1, "score": 1})
+            )
+            .unwrap(),
+            record(json!({"logins": 2, "score": 1.5, "new": 2}))
+        );
+        assert_eq!(
+            apply(
+                json!({"$mul": {"price": 2, "ratio": 0.5, "new": 3}}),
+                json!({"price": 21, "ratio": 3})
+            )
+            .unwrap(),
+            record(json!({"price": 42, "ratio": 1.5, "new": 0}))
+        );
+        assert_eq!(
+            apply(json!({"$inc": {"n": 1}}), json!({"n": i64::MAX})).unwrap(),
+            record(json!({"n": i64::MAX as f64 + 1.0}))
+        );
+    }
+
+    #[test]
+    fn rejects_non_numeric_arithmetic() {
+        assert!(apply(json!({"$inc": {"n": "1"}}), json!({"n": 1})).is_err());
+        assert!(apply(json!({"$mul": {"n": true}}), json!({"n": 1})).is_err());
+
+        let err = apply(json!({"$inc": {"name": 1}}), json!({"name": "bob"})).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CoreError>(),
+            Some(CoreError::ValidationFailed(_))
+        ));
+
+        let err = apply(json!({"$mul": {"tags": 2}}), json!({"tags": []})).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CoreError>(),
+            Some(CoreError::ValidationFailed(_))
+        ));
+    }
+
+    #[test]
+    fn updates_arrays() {
+        assert_eq!(
+            apply(
+                json!({"$push": {"tags": "c", "new": {"$each": [1, 2]}}}),
+                json!({"tags": ["a", "b"]})
+            )
+            .unwrap(),
+            record(json!({"tags": ["a", "b", "c"], "new": [1, 2]}))
+        );
+        assert_eq!(
+            apply(
+                json!({"$addToSet": {"tags": {"$each": ["b", "c", "c"]}}}),
+                json!({"tags": ["a", "b"]})
+            )
+            .unwrap(),
+            record(json!({"tags": ["a", "b", "c"]}))
+        );
+        assert_eq!(
+            apply(
+                json!({"$pull": {"scores": {"$gte": 5}, "tags": "a", "missing": 1}}),
+                json!({"scores": [1, 5, 7, 3], "tags": ["a", "b", "a"]})
+            )
+            .unwrap(),
+            record(json!({"scores": [1, 3], "tags": ["b"]}))
+        );
+        assert!(apply(json!({"$push": {"name": "x"}}), json!({"name": "bob"})).is_err());
+        assert!(apply(json!({"$pull": {"name": "x"}}), json!({"name": "bob"})).is_err());
+    }
+
+    #[test]
+    fn renames_fields() {
+        assert_eq!(
+            apply(
+                json!({"$rename": {"name": "profile.name", "missing": "other"}}),
+                json!({"_id": "1", "name": "bob"})
+            )
+            .unwrap(),
+            record(json!({"_id": "1", "profile": {"name": "bob"}}))
+        );
+        assert!(Update::parse(&json!({"$rename": {"name": 1}})).is_err());
+    }
+
+    #[test]
+    fn rejects_id_changes() {
+        for update in [
+            json!({"$set": {"_id": "2"}}),
+            json!({"$unset": {"_id": ""}}),
+            json!({"$set": {"_id.a": 1}}),
+            json!({"$rename": {"_id": "id"}}),
+            json!({"$rename": {"id": "_id"}}),
+        ] {
+            assert!(Update::parse(&update).is_err(), "{}", update);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_updates() {
+        for update in [
+            json!({}),
+            json!([]),
+            json!({"name": "bob"}),
+            json!({"$set": "bob"}),
+            json!({"$setOnInsert": {"a": 1}}),
+        ] {
+            assert!(Update::parse(&update).is_err(), "{}", update);
+        }
+    }
+
+    #[test]
+    fn leaves_record_untouched_on_failure() {
+        let mut record = record(json!({"n": 1, "name": "bob"}));
+        let update = Update::parse(&json!({"$inc": {"n": 1, "name": 1}})).unwrap();
+
+        assert!(update.apply(&mut record).is_err());
+        assert_eq!(record, self::record(json!({"n": 1, "name": "bob"})));
+        assert!(
+            !Update::parse(&json!({"$set": {"n": 1}}))
+                .unwrap()
+                .apply(&mut record)
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::constants::{MOLECULE_WAL_CHECKPOINT_SIZE, MOLECULE_WAL_PATH};
+use crate::core::atomic::write_atomic;
+use crate::core::data_dir::DataDir;
+
+/// Every log entry is a big-endian `u32` payload length and CRC-32 of the payload, followed by
+/// the payload: a JSON array of [`WalOp`]s.
+const WAL_ENTRY_HEADER_LEN: usize = 8;
+
+/// A change to a single data file, logged with the file's full contents after the change so
+/// that replaying it any number of times gives the same result. Paths are relative to the data
+/// directory, so the log can still be replayed after the data directory is moved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum WalOp {
+    Write { path: String, contents: String },
+    Remove { path: String },
+}
+
+impl WalOp {
+    pub fn write<T: Serialize + ?Sized>(path: String, value: &T) -> Result<Self> {
+        Ok(Self::Write {
+            path,
+            contents: serde_json::to_string(value)?,
+        })
+    }
+
+    fn path(&self) -> &str {
+        match self {
+            Self::Write { path, .. } | Self::Remove { path } => path,
+        }
+    }
+
+    async fn apply(&self, data_dir: &DataDir) -> Result<()> {
+        let path = data_dir.path(self.path());
+
+        match self {
+            Self::Write { contents, .. } => write_atomic(&path, contents).await?,
+            Self::Remove { .. } => match fs::remove_file(&path).await {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            },
+        }
+
+        Ok(())
+    }
+}
+
+/// Write-ahead log making every write to the data files crash safe.
+///
+/// All the file changes of a write are appended to the log as a single entry and synced to disk
+/// before being applied, so a write that was acknowledged is either fully in the data files or
+/// still in the log when the database restarts. Once the log grows past
+/// [`MOLECULE_WAL_CHECKPOINT_SIZE`], the data files are synced and the log is truncated.
+#[derive(Debug)]
+pub struct Wal {
+    data_dir: DataDir,
+    file: File,
+    size: u64,
+    /// Data files changed since the last checkpoint.
+    dirty: BTreeSet<String>,
+}
+
+impl Wal {
+    /// Opens the log of `data_dir`, replaying every complete entry left in it into the data files.
+    /// A torn entry at the end of the log, left by a crash while appending it, was never
+    /// acknowledged and is discarded.
+    pub async fn open(data_dir: DataDir) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(data_dir.path(MOLECULE_WAL_PATH))
+            .await?;
+        let mut log = Vec::new();
+        file.read_to_end(&mut log).await?;
+
+        let mut wal = Self {
+            data_dir,
+            file,
+            size: log.len() as u64,
+            dirty: BTreeSet::new(),
+        };
+        let entries = decode_entries(&log);
+
+        for ops in &entries {
+            wal.apply(ops).await?;
+        }
+
+        if !log.is_empty() {
+            log::info!("Replayed {} write-ahead log entries.", entries.len());
+            wal.checkpoint().await?;
+        }
+
+        Ok(wal)
+    }
+
+    /// Durably logs and then applies the file changes of a single write.
+    pub async fn commit(&mut self, ops: Vec<WalOp>) -> Result<()> {
+        let entry = encode_entry(&ops)?;
+
+        self.file.write_all(&entry).await?;
+        self.file.sync_data().await?;
+        self.size += entry.len() as u64;
+
+        self.apply(&ops).await?;
+
+        if self.size >= MOLECULE_WAL_CHECKPOINT_SIZE {
+            self.checkpoint().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Syncs every data file changed since the last checkpoint and empties the log.
+    pub async fn checkpoint(&mut self) -> Result<()> {
+        let mut dirs = BTreeSet::new();
+
+        for path in &self.dirty {
+            let path = self.data_dir.path(path);
+
+            if let Ok(file) = File::open(&path).await {
+                file.sync_all().await?;
+            }
+
+            if let Some(dir) = Path::new(&path).parent() {
+                dirs.insert(dir.to_path_buf());
+            }
+        }
+
+        // Syncing the directories makes created and removed files durable too.
+        for dir in dirs {
+            File::open(dir).await?.sync_all().await?;
+        }
+
+        self.file.set_len(0).await?;
+        self.file.sync_all().await?;
+        self.size = 0;
+        self.dirty.clear();
+
+        log::info!("Checkpointed the write-ahead log.");
+        Ok(())
+    }
+
+    async fn apply(&mut self, ops: &[WalOp]) -> Result<()> {
+        for op in ops {
+            op.apply(&self.data_dir).await?;
+            self.dirty.insert(op.path().to_string());
+        }
+
+        Ok(())
+    }
+}
+
+fn encode_entry(ops: &[WalOp]) -> Result<Vec<u8>> {
+    let payload = serde_json::to_vec(ops)?;
+    let mut entry = Vec::with_capacity(WAL_ENTRY_HEADER_LEN + payload.len());
+
+    entry.extend((payload.len() as u32).to_be_bytes());
+    entry.extend(crc32(&payload).to_be_bytes());
+    entry.extend(payload);
+
+    Ok(entry)
+}
+
+/// Decodes entries up to the end of the log or the first incomplete or corrupt one.
+fn decode_entries(mut log: &[u8]) -> Vec<Vec<WalOp>> {
+    let mut entries = Vec::new();
+
+    while let Some((header, rest)) = log.split_first_chunk::<WAL_ENTRY_HEADER_LEN>() {
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let checksum = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+
+        let Some(payload) = rest.get(..len) else {
+            break;
+        };
+
+        if crc32(payload) != checksum {
+            break;
+        }
+
+        let Ok(ops) = serde_json::from_slice(payload) else {
+            break;
+        };
+
+        entries.push(ops);
+        log = &rest[len..];
+    }
+
+    if !log.is_empty() {
+        log::warn!(
+            "Discarded {} bytes of incomplete write-ahead log entries.",
+            log.len()
+        );
+    }
+
+    entries
+}
+
+/// CRC-32 (IEEE) checksum.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in bytes {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A data directory whose log holds `log`, as left by a crash.
+    async fn crashed_data_dir(log: &[u8]) -> DataDir {
+        let data_dir = DataDir::temp();
+
+        fs::create_dir_all(data_dir.root()).await.unwrap();
+        fs::write(data_dir.path(MOLECULE_WAL_PATH), log)
+            .await
+            .unwrap();
+
+        data_dir
+    }
+
+    fn write(path: &str, contents: &str) -> WalOp {
+        WalOp::Write {
+            path: path.to_string(),
+            contents: contents.to_string(),
+        }
+    }
+
+    async fn read(data_dir: &DataDir, path: &str) -> Option<String> {
+        fs::read_to_string(data_dir.path(path)).await.ok()
+    }
+
+    async fn log_len(data_dir: &DataDir) -> u64 {
+        fs::metadata(data_dir.path(MOLECULE_WAL_PATH))
+            .await
+            .unwrap()
+            .len()
+    }
+
+    #[tokio::test]
+    async fn replays_logged_writes_after_a_crash() {
+        let mut log = encode_entry(&[write("a.json", "1"), write("b.json", "1")]).unwrap();
+        log.extend(
+            encode_entry(&[
+                write("a.json", "2"),
+                WalOp::Remove {
+                    path: "b.json".to_string(),
+                },
+            ])
+            .unwrap(),
+        );
+        let data_dir = crashed_data_dir(&log).await;
+
+        Wal::open(data_dir.clone()).await.unwrap();
+
+        assert_eq!(read(&data_dir, "a.json").await.as_deref(), Some("2"));
+        assert_eq!(read(&data_dir, "b.json").await, None);
+        assert_eq!(log_len(&data_dir).await, 0);
+
+        fs::remove_dir_all(data_dir.root()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn discards_torn_and_corrupt_tail_entries() {
+        let complete = encode_entry(&[write("a.json", "1")]).unwrap();
+        let tail = encode_entry(&[write("a.json", "2"), write("b.json", "2")]).unwrap();
+
+        let torn = [&complete[..], &tail[..tail.len() - 3]].concat();
+        let mut corrupt = [&complete[..], &tail[..]].concat();
+        *corrupt.last_mut().unwrap() ^= 0xff;
+
+        for log in [torn, corrupt] {
+            let data_dir = crashed_data_dir(&log).await;
+
+            Wal::open(data_dir.clone()).await.unwrap();
+
+            assert_eq!(read(&data_dir, "a.json").await.as_deref(), Some("1"));
+            assert_eq!(read(&data_dir, "b.json").await, None);
+            assert_eq!(log_len(&data_dir).await, 0);
+
+            fs::remove_dir_all(data_dir.root()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn checkpoints_truncate_the_log() {
+        let data_dir = crashed_data_dir(&[]).await;
+        let mut wal = Wal::open(data_dir.clone()).await.unwrap();
+
+        wal.commit(vec![write("a.json", "1")]).await.unwrap();
+
+        assert_eq!(read(&data_dir, "a.json").await.as_deref(), Some("1"));
+        assert_eq!(log_len(&data_dir).await, wal.size);
+
+        wal.checkpoint().await.unwrap();
+
+        assert_eq!(log_len(&data_dir).await, 0);
+        assert!(wal.dirty.is_empty());
+
+        fs::remove_dir_all(data_dir.root()).await.unwrap();
+    }
+
+    #[test]
+    fn computes_ieee_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
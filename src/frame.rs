@@ -0,0 +1,201 @@
+use std::fmt;
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::constants::MOLECULE_MAX_DISCARDED_FRAME_FACTOR;
+
+/// Every frame on the wire is a big-endian `u32` payload length followed by the payload.
+pub const FRAME_HEADER_LEN: usize = 4;
+
+#[derive(Debug)]
+pub enum FrameError {
+    /// The frame declared a payload larger than the configured maximum. On reads the payload is
+    /// discarded, so the stream stays in sync and the next frame can still be read.
+    TooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// The frame declared a payload so far over the maximum that it isn't worth discarding. The
+    /// payload is left unread, so the connection must be closed.
+    PayloadTooLarge {
+        size: usize,
+        max: usize,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge { size, max } => write!(
+                f,
+                "Frame of {} bytes exceeds the maximum frame size of {} bytes.",
+                size, max
+            ),
+            Self::PayloadTooLarge { size, max } => write!(
+                f,
+                "Frame of {} bytes is too far over the maximum frame size of {} bytes to discard.",
+                size, max
+            ),
+            Self::Io(err) => write!(f, "Frame I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<io::Error> for FrameError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// Whether `buf` already holds at least one complete frame, meaning the next [`read_frame`]
+/// can be answered without waiting on the peer.
+pub fn has_buffered_frame(buf: &[u8]) -> bool {
+    let Some(header) = buf.first_chunk::<FRAME_HEADER_LEN>() else {
+        return false;
+    };
+
+    buf.len() - FRAME_HEADER_LEN >= u32::from_be_bytes(*header) as usize
+}
+
+/// Reads a single frame, returning `None` if the peer closed the connection cleanly before
+/// sending another frame.
+///
+/// Oversized frames up to [`MOLECULE_MAX_DISCARDED_FRAME_FACTOR`] times the maximum are read
+/// and discarded. Larger ones are left unread, so a peer can't keep the server reading
+/// gigabytes it will throw away.
+pub async fn read_frame<R>(
+    reader: &mut R,
+    max_frame_size: usize,
+) -> Result<Option<Vec<u8>>, FrameError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; FRAME_HEADER_LEN];
+
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+
+    let size = u32::from_be_bytes(header) as usize;
+
+    if size > max_frame_size.saturating_mul(MOLECULE_MAX_DISCARDED_FRAME_FACTOR) {
+        return Err(FrameError::PayloadTooLarge {
+            size,
+            max: max_frame_size,
+        });
+    }
+
+    if size > max_frame_size {
+        tokio::io::copy(
+            &mut (&mut *reader).take(size as u64),
+            &mut tokio::io::sink(),
+        )
+        .await?;
+        return Err(FrameError::TooLarge {
+            size,
+            max: max_frame_size,
+        });
+    }
+
+    let mut payload = vec![0u8; size];
+    reader.read_exact(&mut payload).await?;
+
+    Ok(Some(payload))
+}
+
+/// Writes `payload` as a single frame.
+pub async fn write_frame<W>(writer: &mut W, payload: &[u8]) -> Result<(), FrameError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let Ok(size) = u32::try_from(payload.len()) else {
+        return Err(FrameError::TooLarge {
+            size: payload.len(),
+            max: u32::MAX as usize,
+        });
+    };
+
+    writer.write_all(&size.to_be_bytes()).await?;
+    writer.write_all(payload).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn frames(payloads: &[&[u8]]) -> Vec<u8> {
+        let mut buf = Vec::new();
+
+        for payload in payloads {
+            write_frame(&mut buf, payload).await.unwrap();
+        }
+
+        buf
+    }
+
+    #[tokio::test]
+    async fn round_trips_frames() {
+        let buf = frames(&[b"COLLECTIONS_LIST", b""]).await;
+        let mut reader = buf.as_slice();
+
+        assert_eq!(&buf[..FRAME_HEADER_LEN], &16u32.to_be_bytes());
+        assert!(has_buffered_frame(reader));
+        assert_eq!(
+            read_frame(&mut reader, 64).await.unwrap().unwrap(),
+            b"COLLECTIONS_LIST"
+        );
+        assert!(has_buffered_frame(reader));
+        assert_eq!(read_frame(&mut reader, 64).await.unwrap().unwrap(), b"");
+        assert!(!has_buffered_frame(reader));
+        assert!(read_frame(&mut reader, 64).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn partial_frames_are_not_buffered() {
+        let buf = frames(&[b"NOOP"]).await;
+
+        assert!(!has_buffered_frame(&buf[..2]));
+        assert!(!has_buffered_frame(&buf[..FRAME_HEADER_LEN + 2]));
+
+        // A peer closing mid length prefix closed before sending another frame.
+        assert!(read_frame(&mut &buf[..2], 64).await.unwrap().is_none());
+        assert!(matches!(
+            read_frame(&mut &buf[..FRAME_HEADER_LEN + 2], 64).await,
+            Err(FrameError::Io(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn discards_frames_over_the_maximum() {
+        let buf = frames(&[&[b'x'; 10], b"NOOP"]).await;
+        let mut reader = buf.as_slice();
+
+        assert!(matches!(
+            read_frame(&mut reader, 8).await,
+            Err(FrameError::TooLarge { size: 10, max: 8 })
+        ));
+        assert_eq!(read_frame(&mut reader, 8).await.unwrap().unwrap(), b"NOOP");
+    }
+
+    #[tokio::test]
+    async fn refuses_frames_too_far_over_the_maximum() {
+        let size = 8 * MOLECULE_MAX_DISCARDED_FRAME_FACTOR + 1;
+        let buf = frames(&[&vec![b'x'; size], b"NOOP"]).await;
+        let mut reader = buf.as_slice();
+
+        assert!(matches!(
+            read_frame(&mut reader, 8).await,
+            Err(FrameError::PayloadTooLarge { max: 8, .. })
+        ));
+        // The payload is left unread, so the stream is out of sync.
+        assert_eq!(reader.len(), buf.len() - FRAME_HEADER_LEN);
+    }
+}
@@ -1,5 +1,7 @@
+use std::net::IpAddr;
 use std::process;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Result, bail};
 use clap::Parser;
@@ -7,27 +9,42 @@ use tokio::fs;
 
 use crate::auth::MoleculeAuthApi;
 use crate::cli::MoleculeCliApi;
+use crate::compaction::MoleculeCompactionApi;
+use crate::config::Config;
 use crate::constants::{
-    MOLECULE_DEFAULT_ADDR, MOLECULE_DEFAULT_DATA_COLLECTION_META_PATH,
-    MOLECULE_DEFAULT_DATA_COLLECTIONS_PATH, MOLECULE_DEFAULT_DATA_PATH, MOLECULE_DEFAULT_PORT,
-    MOLECULE_DOT_FILE_PATH,
+    MOLECULE_DATA_COLLECTION_META_PATH, MOLECULE_DATA_ENGINE_PATH, MOLECULE_DATA_PATH,
+    MOLECULE_DEFAULT_ADDR, MOLECULE_DEFAULT_CACHE_SIZE, MOLECULE_DEFAULT_COMPACTION_INTERVAL_SECS,
+    MOLECULE_DEFAULT_DATA_DIR, MOLECULE_DEFAULT_MAX_FRAME_SIZE, MOLECULE_DEFAULT_PORT,
+    MOLECULE_DEFAULT_TTL_SWEEP_INTERVAL_SECS,
 };
+use crate::core::atomic::write_atomic;
+use crate::core::consistency::MoleculeCoreConsistencyApi;
+use crate::core::data_dir::DataDir;
+use crate::core::storage::{StorageBackend, StorageEngine, memory::MemoryStorage};
+use crate::core::wal::Wal;
 use crate::tcp::MoleculeTcpApi;
+use crate::ttl::MoleculeTtlApi;
 use crate::{args::Args, molecule::Molecule};
 
 mod args;
 mod auth;
 mod cli;
+mod compaction;
+mod config;
 mod constants;
 mod core;
+mod frame;
 mod molecule;
 mod proto;
+mod session;
 mod tcp;
+mod ttl;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     if let Err(e) = run().await {
-        log::error!("{}", e.to_string());
+        log::error!("{}", e);
+        eprintln!("{}", e);
         process::exit(1);
     }
 
@@ -35,7 +52,7 @@ async fn main() -> Result<()> {
 }
 
 async fn run() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
 
     if args.enable_logging {
         env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("molecule"))
@@ -43,28 +60,65 @@ async fn run() -> Result<()> {
         log::info!("Logging enabled.");
     }
 
-    if !fs::try_exists(MOLECULE_DEFAULT_DATA_PATH).await? {
-        fs::create_dir_all(MOLECULE_DEFAULT_DATA_PATH).await?;
+    if let Some(config_path) = &args.config {
+        let config = Config::load(config_path).await?;
+        args = args.with_config(config);
     }
 
-    if !fs::try_exists(MOLECULE_DOT_FILE_PATH).await? {
-        fs::create_dir_all(MOLECULE_DOT_FILE_PATH).await?;
-    }
+    // The lock is held until the database stops.
+    let (storage, _data_dir_lock) = if args.in_memory {
+        log::info!("Keeping all data in memory, nothing will be persisted.");
+        (StorageBackend::Memory(MemoryStorage::default()), None)
+    } else {
+        let data_dir = DataDir::new(
+            args.data_dir
+                .as_deref()
+                .unwrap_or(MOLECULE_DEFAULT_DATA_DIR),
+        );
 
-    if !fs::try_exists(MOLECULE_DEFAULT_DATA_COLLECTIONS_PATH).await? {
-        fs::create_dir_all(MOLECULE_DEFAULT_DATA_COLLECTIONS_PATH).await?;
-    }
+        fs::create_dir_all(data_dir.path(MOLECULE_DATA_PATH)).await?;
 
-    if !fs::try_exists(MOLECULE_DEFAULT_DATA_COLLECTION_META_PATH).await? {
-        fs::write(MOLECULE_DEFAULT_DATA_COLLECTION_META_PATH, b"[]").await?;
-    }
+        let lock = data_dir.lock()?;
+        let storage = open_storage(&data_dir, args.storage_engine).await?;
+
+        log::info!("Using data directory {}.", data_dir.root());
+        (storage, Some(lock))
+    };
 
     let addr = args.addr.unwrap_or(MOLECULE_DEFAULT_ADDR.to_string());
     let port = args.port.unwrap_or(MOLECULE_DEFAULT_PORT);
+    let max_frame_size = args
+        .max_frame_size
+        .unwrap_or(MOLECULE_DEFAULT_MAX_FRAME_SIZE);
+    let ttl_sweep_interval = args
+        .ttl_sweep_interval
+        .unwrap_or(MOLECULE_DEFAULT_TTL_SWEEP_INTERVAL_SECS);
+    let compaction_interval = args
+        .compaction_interval
+        .unwrap_or(MOLECULE_DEFAULT_COMPACTION_INTERVAL_SECS);
+    let cache_size = args.cache_size.unwrap_or(MOLECULE_DEFAULT_CACHE_SIZE);
+
+    if ttl_sweep_interval == 0 {
+        bail!("The TTL sweep interval must be at least one second.");
+    }
 
-    let molecule = Molecule::new(addr, port);
+    if compaction_interval == 0 {
+        bail!("The compaction interval must be at least one second.");
+    }
+
+    let molecule = Molecule::new(
+        addr,
+        port,
+        max_frame_size,
+        Duration::from_secs(ttl_sweep_interval),
+        Duration::from_secs(compaction_interval),
+        cache_size,
+        storage,
+    );
     let shared_molecule = Arc::new(molecule);
 
+    shared_molecule.check_consistency(args.repair).await?;
+
     if let Some(auth_str) = args.auth {
         let Some((username, password)) = auth_str.split_once(":") else {
             bail!("Could not parse auth string for username and password.")
@@ -73,6 +127,19 @@ async fn run() -> Result<()> {
         shared_molecule
             .setup_user(username.to_owned(), password.to_owned())
             .await?;
+    } else {
+        shared_molecule.load_users().await?;
+    }
+
+    if !shared_molecule.auth_required().await {
+        if !is_loopback_addr(&shared_molecule.addr) && !args.allow_unauthenticated {
+            bail!(
+                "Refusing to listen on {} without auth. Set up a user with --auth, bind to a loopback address with --addr, or pass --allow-unauthenticated.",
+                shared_molecule.addr
+            );
+        }
+
+        log::warn!("Auth is not set up, any client can connect to the database.");
     }
 
     let server_handle = shared_molecule.clone();
@@ -82,9 +149,101 @@ async fn run() -> Result<()> {
         }
     });
 
+    let ttl_handle = shared_molecule.clone();
+    tokio::spawn(async move { ttl_handle.start_ttl_monitor().await });
+
+    let compaction_handle = shared_molecule.clone();
+    tokio::spawn(async move { compaction_handle.start_compaction_monitor().await });
+
     if args.cli {
         shared_molecule.start_cli().await?;
     }
 
     Ok(())
 }
+
+/// Opens the storage of the data directory, setting it up on first start.
+async fn open_storage(
+    data_dir: &DataDir,
+    requested: Option<StorageEngine>,
+) -> Result<StorageBackend> {
+    let storage_engine = resolve_storage_engine(data_dir, requested).await?;
+    let map_path = data_dir.path(MOLECULE_DATA_COLLECTION_META_PATH);
+
+    if !fs::try_exists(&map_path).await? {
+        write_atomic(&map_path, b"[]").await?;
+    }
+
+    let wal = Wal::open(data_dir.clone()).await?;
+
+    StorageBackend::open(storage_engine, data_dir.clone(), wal).await
+}
+
+/// Picks the storage engine of the data directory. The engine is recorded when the data directory
+/// is created and can't be changed afterwards; data directories from before engines could be
+/// picked are stored as JSON.
+async fn resolve_storage_engine(
+    data_dir: &DataDir,
+    requested: Option<StorageEngine>,
+) -> Result<StorageEngine> {
+    let engine_path = data_dir.path(MOLECULE_DATA_ENGINE_PATH);
+
+    let recorded = if fs::try_exists(&engine_path).await? {
+        let name = fs::read_to_string(&engine_path).await?;
+        Some(StorageEngine::parse(&name)?)
+    } else if fs::try_exists(data_dir.path(MOLECULE_DATA_COLLECTION_META_PATH)).await? {
+        Some(StorageEngine::Json)
+    } else {
+        None
+    };
+
+    if let (Some(recorded), Some(requested)) = (recorded, requested)
+        && recorded != requested
+    {
+        bail!(
+            "The data directory is stored with the {} storage engine and can't be opened with {}.",
+            recorded.name(),
+            requested.name()
+        );
+    }
+
+    let engine = recorded.or(requested).unwrap_or(StorageEngine::Segment);
+
+    if !fs::try_exists(&engine_path).await? {
+        write_atomic(&engine_path, engine.name()).await?;
+    }
+
+    log::info!("Using the {} storage engine.", engine.name());
+    Ok(engine)
+}
+
+/// Whether the server only accepts connections from the machine it runs on when bound to
+/// `addr`.
+fn is_loopback_addr(addr: &str) -> bool {
+    addr == "localhost"
+        || addr
+            .parse::<IpAddr>()
+            .is_ok_and(|ip_addr| ip_addr.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_loopback_addresses_count_as_local() {
+        for addr in ["localhost", "127.0.0.1", "127.0.0.53", "::1"] {
+            assert!(is_loopback_addr(addr), "{}", addr);
+        }
+
+        for addr in [
+            "0.0.0.0",
+            "::",
+            "192.168.1.10",
+            "example.com",
+            "localhost:7979",
+        ] {
+            assert!(!is_loopback_addr(addr), "{}", addr);
+        }
+    }
+}
@@ -1,20 +1,217 @@
-use tokio::sync::RwLock;
+use std::collections::HashMap;
+use std::sync::{self, Arc, MutexGuard};
+use std::time::Duration;
 
-use crate::proto::AuthInfo;
+use tokio::sync::{Mutex, OwnedMutexGuard, OwnedRwLockWriteGuard, RwLock};
+
+use crate::auth::UserStore;
+use crate::core::cache::CollectionCache;
+use crate::core::storage::StorageBackend;
 
 #[derive(Debug)]
 pub struct Molecule {
     pub addr: String,
     pub port: u32,
-    pub active_user: RwLock<Option<AuthInfo>>,
+    /// Largest TCP frame payload (in bytes) accepted from clients.
+    pub max_frame_size: usize,
+    /// How often expired records are removed through TTL indexes.
+    pub ttl_sweep_interval: Duration,
+    /// How often collections with enough space to reclaim are compacted.
+    pub compaction_interval: Duration,
+    /// Users clients authenticate as and the custom roles they may hold.
+    pub users: RwLock<UserStore>,
+    /// Storage engine the collections are persisted with.
+    pub storage: StorageBackend,
+    /// Serializes changes to the collection map against each other and against readers of it.
+    pub collection_map_lock: RwLock<()>,
+    /// Per-collection locks, held for reading by queries and for writing by anything that
+    /// changes a collection's records or indexes, so concurrent writes are never lost.
+    collection_locks: sync::Mutex<HashMap<String, Arc<RwLock<()>>>>,
+    /// Per-collection locks held by writes and by compactions, which can't run alongside writes
+    /// but mustn't hold up queries while they wait or run.
+    collection_write_locks: sync::Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    /// Collections held in memory, read from disk only when they aren't cached.
+    cache: sync::Mutex<CollectionCache>,
 }
 
 impl Molecule {
-    pub fn new(addr: String, port: u32) -> Self {
+    pub fn new(
+        addr: String,
+        port: u32,
+        max_frame_size: usize,
+        ttl_sweep_interval: Duration,
+        compaction_interval: Duration,
+        cache_size: usize,
+        storage: StorageBackend,
+    ) -> Self {
         Self {
             addr,
             port,
-            active_user: RwLock::new(None),
+            max_frame_size,
+            ttl_sweep_interval,
+            compaction_interval,
+            users: RwLock::new(UserStore::default()),
+            storage,
+            collection_map_lock: RwLock::new(()),
+            collection_locks: sync::Mutex::new(HashMap::new()),
+            collection_write_locks: sync::Mutex::new(HashMap::new()),
+            cache: sync::Mutex::new(CollectionCache::new(cache_size)),
+        }
+    }
+
+    /// The lock guarding the collection with the given ID. When both are needed, the collection
+    /// map lock must be acquired first.
+    pub fn collection_lock(&self, collection_id: &str) -> Arc<RwLock<()>> {
+        lock_for(&self.collection_locks, collection_id)
+    }
+
+    /// The lock serializing writes to the collection with the given ID against each other and
+    /// against its compaction. It's acquired after the collection map lock and before the
+    /// collection lock.
+    pub fn collection_write_lock(&self, collection_id: &str) -> Arc<Mutex<()>> {
+        lock_for(&self.collection_write_locks, collection_id)
+    }
+
+    /// Locks the collection with the given ID for a write to its records or indexes.
+    pub async fn lock_collection_for_write(&self, collection_id: &str) -> CollectionWriteGuard {
+        let writes = self.collection_write_lock(collection_id).lock_owned().await;
+        let records = self.collection_lock(collection_id).write_owned().await;
+
+        CollectionWriteGuard {
+            _records: records,
+            _writes: writes,
         }
     }
+
+    pub fn cache(&self) -> MutexGuard<'_, CollectionCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Forgets the locks of a deleted collection.
+    pub fn forget_collection_lock(&self, collection_id: &str) {
+        self.collection_locks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(collection_id);
+        self.collection_write_locks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(collection_id);
+    }
+}
+
+/// The lock of a collection in `locks`, created if there's none. Whenever one is created, the
+/// locks nobody holds or waits on are dropped, so clients sending made up collection IDs can't
+/// grow the map past the collections in use.
+fn lock_for<T: Default>(
+    locks: &sync::Mutex<HashMap<String, Arc<T>>>,
+    collection_id: &str,
+) -> Arc<T> {
+    let mut locks = locks.lock().unwrap_or_else(|e| e.into_inner());
+
+    if let Some(lock) = locks.get(collection_id) {
+        return lock.clone();
+    }
+
+    locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+
+    let lock = Arc::new(T::default());
+    locks.insert(collection_id.to_string(), lock.clone());
+
+    lock
+}
+
+/// Both locks of a collection, held for the duration of a write to it.
+pub struct CollectionWriteGuard {
+    _records: OwnedRwLockWriteGuard<()>,
+    _writes: OwnedMutexGuard<()>,
+}
+
+#[cfg(test)]
+impl Molecule {
+    /// A database keeping everything in memory with the default options, for tests.
+    pub fn in_memory() -> Self {
+        use crate::core::storage::memory::MemoryStorage;
+
+        Self::with_storage(StorageBackend::Memory(MemoryStorage::default()))
+    }
+
+    /// A database stored in `data_dir` with the given engine and the default options, for
+    /// tests. The data directory is set up like on first start if it's empty.
+    pub async fn on_disk(
+        engine: crate::core::storage::StorageEngine,
+        data_dir: crate::core::data_dir::DataDir,
+    ) -> Self {
+        use crate::constants::{MOLECULE_DATA_COLLECTION_META_PATH, MOLECULE_DATA_PATH};
+        use crate::core::wal::Wal;
+
+        let map_path = data_dir.path(MOLECULE_DATA_COLLECTION_META_PATH);
+
+        tokio::fs::create_dir_all(data_dir.path(MOLECULE_DATA_PATH))
+            .await
+            .unwrap();
+
+        if !tokio::fs::try_exists(&map_path).await.unwrap() {
+            tokio::fs::write(map_path, "[]").await.unwrap();
+        }
+
+        let wal = Wal::open(data_dir.clone()).await.unwrap();
+
+        Self::with_storage(StorageBackend::open(engine, data_dir, wal).await.unwrap())
+    }
+
+    /// A database using `storage` with the default options, for tests.
+    pub fn with_storage(storage: StorageBackend) -> Self {
+        use crate::constants::{
+            MOLECULE_DEFAULT_CACHE_SIZE, MOLECULE_DEFAULT_COMPACTION_INTERVAL_SECS,
+            MOLECULE_DEFAULT_MAX_FRAME_SIZE, MOLECULE_DEFAULT_TTL_SWEEP_INTERVAL_SECS,
+        };
+
+        Self::new(
+            "127.0.0.1".to_string(),
+            0,
+            MOLECULE_DEFAULT_MAX_FRAME_SIZE,
+            Duration::from_secs(MOLECULE_DEFAULT_TTL_SWEEP_INTERVAL_SECS),
+            Duration::from_secs(MOLECULE_DEFAULT_COMPACTION_INTERVAL_SECS),
+            MOLECULE_DEFAULT_CACHE_SIZE,
+            storage,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::record::MoleculeCoreRecordsApi;
+
+    #[tokio::test]
+    async fn unused_collection_locks_are_dropped() {
+        let molecule = Molecule::in_memory();
+
+        for i in 0..100 {
+            let collection_id = format!("missing-{}", i);
+
+            assert!(
+                molecule
+                    .get_record_by_id(collection_id.clone(), "a".into())
+                    .await
+                    .is_err()
+            );
+            assert!(
+                molecule
+                    .delete_record(collection_id, "a".into())
+                    .await
+                    .is_err()
+            );
+        }
+
+        let held = molecule.collection_lock("held");
+        let _guard = held.read().await;
+        molecule.collection_lock("other");
+
+        let locks = molecule.collection_locks.lock().unwrap();
+        assert!(locks.len() <= 2);
+        assert!(locks.contains_key("held"));
+        assert!(molecule.collection_write_locks.lock().unwrap().len() <= 1);
+    }
 }
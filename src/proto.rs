@@ -1,14 +1,19 @@
 use std::collections::HashMap;
+use std::fmt;
 
-use anyhow::{Result, bail};
-use serde::{Deserialize, Serialize};
+use anyhow::{Result, anyhow, bail};
+use serde::Serialize;
 use serde_json::Value;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AuthInfo {
-    pub username: String,
-    pub password: String,
-}
+use crate::auth::Permission;
+use crate::constants::{
+    MOLECULE_MAX_REQUEST_TAG_LEN, MOLECULE_MIN_PROTOCOL_VERSION, MOLECULE_PROTOCOL_VERSION,
+};
+use crate::core::error::CoreError;
+use crate::core::index::IndexOptions;
+use crate::core::query::{Filter, QueryOptions};
+use crate::core::record::InsertOptions;
+use crate::core::update::{Update, UpdateOptions};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum DatabaseInputType {
@@ -16,27 +21,73 @@ pub enum DatabaseInputType {
     Stop,
     /// Do nothing, empty request.
     Noop,
+    /// End the current TCP session.
+    Quit,
     /// List all collections in the database.
     CollectionsList,
     /// Get collection name from ID.
     Collection(String),
-    /// Get records of a collection by the collection ID.
-    CollectionRecords(String),
+    /// Get records of a collection by the collection ID, sorted, projected and paginated by the
+    /// options.
+    CollectionRecords(String, QueryOptions),
     /// Get record of a collection (referenced by collection_id) by the record ID.
     IdRecord(String, String),
+    /// Get records of a collection (referenced by collection_id) matching a filter, sorted,
+    /// projected and paginated by the options.
+    FindRecords(String, Filter, QueryOptions),
     /// Create a collection with a provided collection_name.
     CreateCollection(String),
     /// Create a record in a specific collection (referenced by collection_id) with contents.
     CreateRecord(String, HashMap<String, Value>),
+    /// Create many records in a specific collection (referenced by collection_id) at once.
+    CreateRecords(String, Vec<HashMap<String, Value>>, InsertOptions),
+    /// Apply update operators to a record in a specific collection (referenced by collection_id)
+    /// with it's record ID.
+    UpdateRecord(String, String, Update),
+    /// Replace the contents of a record in a specific collection (referenced by collection_id)
+    /// with it's record ID, keeping the `_id`.
+    ReplaceRecord(String, String, HashMap<String, Value>),
+    /// Apply update operators to every record matching a filter in a specific collection
+    /// (referenced by collection_id), optionally upserting when nothing matches.
+    UpdateRecords(String, Filter, Update, UpdateOptions),
+    /// Delete every record matching a filter in a specific collection (referenced by
+    /// collection_id).
+    DeleteRecords(String, Filter),
+    /// Index one or more fields of the records in a specific collection (referenced by
+    /// collection_id).
+    CreateIndex(String, Vec<String>, IndexOptions),
+    /// Drop an index of a specific collection (referenced by collection_id) by it's name.
+    DropIndex(String, String),
+    /// List the indexes of a specific collection (referenced by collection_id).
+    ListIndexes(String),
     /// Delete a collection referenced by it's collection ID.
     DeleteCollection(String),
     /// Delete a record in a specific collection (referenced by collection_id) with it's record ID.
     DeleteRecord(String, String),
+    /// Reclaim the space taken by deleted and superseded records of a collection (referenced by
+    /// collection_id).
+    Compact(String),
+    /// Create a user with a username, password and role.
+    CreateUser(String, String, String),
+    /// Drop a user by it's username.
+    DropUser(String),
+    /// Change the password of a user referenced by it's username.
+    ChangePassword(String, String),
+    /// List all users with their roles.
+    ListUsers,
+    /// Create a custom role with a name, granting permissions.
+    CreateRole(String, Vec<Permission>),
+    /// Drop a custom role by it's name.
+    DropRole(String),
+    /// List all built-in and custom roles with their permissions.
+    ListRoles,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum DatabaseOutputMsg {
     Noop,
+    /// Sent right before the server closes a session after `QUIT`.
+    Bye,
     Err(DatabaseOutputError),
     /// Collections(Stringified JSON of the collections)
     Collections(String),
@@ -48,28 +99,94 @@ pub enum DatabaseOutputMsg {
     CreatedCollection(String),
     /// CreatedRecord(ID of the record)
     CreatedRecord(String),
+    /// CreatedRecords(Stringified JSON of the inserted IDs and per-record errors)
+    CreatedRecords(String),
+    /// UpdatedRecord(Stringified JSON of the record after the update)
+    UpdatedRecord(String),
     /// DeletedCollection(ID of the collection)
     DeletedCollection(String),
     /// DeletedRecord(ID of the record)
     DeletedRecord(String),
+    /// UpdateResult(Stringified JSON of the matched, modified and upserted counts)
+    UpdateResult(String),
+    /// DeleteResult(Stringified JSON of the deleted count)
+    DeleteResult(String),
+    /// Indexes(Stringified JSON of the index definitions)
+    Indexes(String),
+    /// CreatedIndex(Name of the index)
+    CreatedIndex(String),
+    /// DroppedIndex(Name of the index)
+    DroppedIndex(String),
+    /// CreatedUser(Username of the user)
+    CreatedUser(String),
+    /// DroppedUser(Username of the user)
+    DroppedUser(String),
+    /// ChangedPassword(Username of the user)
+    ChangedPassword(String),
+    /// Users(Stringified JSON of the usernames and roles)
+    Users(String),
+    /// CreatedRole(Name of the role)
+    CreatedRole(String),
+    /// DroppedRole(Name of the role)
+    DroppedRole(String),
+    /// Roles(Stringified JSON of the roles and their permissions)
+    Roles(String),
 }
 
+/// Sent as `ERR <code> <message>`, where the code is stable for clients to branch on and the
+/// message is meant for humans.
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum DatabaseOutputError {
-    InvalidInput,
+    /// InvalidInput(Why the request couldn't be parsed)
+    InvalidInput(String),
     CmdNotAvailable,
+    /// FrameTooLarge(Size of the frame, largest size accepted)
+    FrameTooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// PayloadTooLarge(Size of the frame, largest size accepted), sent right before the server
+    /// closes the session.
+    PayloadTooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// Core(Failure of the requested operation)
+    Core(CoreError),
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum HandShakeInputMsg {
+    /// Speak protocol version 1, as clients from before version negotiation do.
     Ok,
+    /// Pick a protocol version out of the ones advertised by `INITCONN`.
+    Hello,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum HandShakeOutputMsg {
-    InitConn,
+    /// InitConn(What the server supports, advertised to the client)
+    InitConn(ServerCapabilities),
     Err(HandShakeOutputError),
-    Ready,
+    /// Ready(Protocol version picked with `HELLO`, none for `OK`)
+    Ready(Option<u32>),
+}
+
+/// Advertised as JSON along with `INITCONN`, so clients can pick a protocol version and only
+/// use what the server supports.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct ServerCapabilities {
+    /// Newest protocol version the server speaks.
+    pub version: u32,
+    /// Oldest protocol version the server still speaks.
+    pub min_version: u32,
+    pub framing: &'static str,
+    pub max_frame_size: usize,
+    pub compression: &'static [&'static str],
+    pub auth: &'static [&'static str],
+    /// Whether the handshake must carry credentials.
+    pub auth_required: bool,
+    pub commands: &'static [&'static str],
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -79,6 +196,10 @@ pub enum HandShakeOutputError {
     MalformedAuthStr,
     MalformedRequest,
     IncorrectAuthInfo,
+    FrameTooLarge,
+    PayloadTooLarge,
+    UnsupportedVersion,
+    AuthRequired,
 }
 
 impl TryFrom<&str> for HandShakeInputMsg {
@@ -87,6 +208,7 @@ impl TryFrom<&str> for HandShakeInputMsg {
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match value {
             "OK" => Ok(Self::Ok),
+            "HELLO" => Ok(Self::Hello),
             _ => Err(HandShakeOutputError::InvalidHandShakeMsg),
         }
     }
@@ -95,36 +217,125 @@ impl TryFrom<&str> for HandShakeInputMsg {
 impl HandShakeOutputError {
     pub fn as_str(&self) -> &'static str {
         match self {
-            Self::InvalidHandShake => "ERR invalid_handshake\n",
-            Self::InvalidHandShakeMsg => "ERR invalid_handshake_msg\n",
-            Self::MalformedAuthStr => "ERR malformed_auth_str\n",
-            Self::MalformedRequest => "ERR malformed_request\n",
-            Self::IncorrectAuthInfo => "ERR incorrect_auth_info\n",
+            Self::InvalidHandShake => "ERR invalid_handshake",
+            Self::InvalidHandShakeMsg => "ERR invalid_handshake_msg",
+            Self::MalformedAuthStr => "ERR malformed_auth_str",
+            Self::MalformedRequest => "ERR malformed_request",
+            Self::IncorrectAuthInfo => "ERR incorrect_auth_info",
+            Self::FrameTooLarge => "ERR frame_too_large",
+            Self::PayloadTooLarge => "ERR payload_too_large",
+            Self::UnsupportedVersion => "ERR unsupported_version",
+            Self::AuthRequired => "ERR auth_required",
         }
     }
 }
 
 impl HandShakeOutputMsg {
-    pub fn as_str(&self) -> &'static str {
+    pub fn to_bytes(&self) -> Vec<u8> {
         match self {
-            Self::InitConn => "INITCONN\n",
-            Self::Err(err) => err.as_str(),
-            Self::Ready => "READY\n",
+            Self::InitConn(capabilities) => format!(
+                "INITCONN {}",
+                serde_json::to_string(capabilities).unwrap_or_default()
+            )
+            .into_bytes(),
+            Self::Err(err) => err.as_str().as_bytes().to_vec(),
+            Self::Ready(None) => b"READY".to_vec(),
+            Self::Ready(Some(version)) => format!("READY {}", version).into_bytes(),
         }
     }
 }
 
-impl<'a> Into<&'a [u8]> for HandShakeOutputMsg {
-    fn into(self) -> &'a [u8] {
-        self.as_str().as_bytes()
+impl ServerCapabilities {
+    pub fn new(max_frame_size: usize, auth_required: bool) -> Self {
+        Self {
+            version: MOLECULE_PROTOCOL_VERSION,
+            min_version: MOLECULE_MIN_PROTOCOL_VERSION,
+            framing: "length_prefixed",
+            max_frame_size,
+            compression: &["none"],
+            auth: &["plain"],
+            auth_required,
+            commands: TCP_COMMANDS,
+        }
+    }
+
+    pub fn supports_version(&self, version: u32) -> bool {
+        (self.min_version..=self.version).contains(&version)
     }
 }
 
 impl DatabaseOutputError {
-    pub fn as_str(&self) -> &'static str {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidInput(_) => "invalid_input",
+            Self::CmdNotAvailable => "cmd_not_available",
+            Self::FrameTooLarge { .. } => "frame_too_large",
+            Self::PayloadTooLarge { .. } => "payload_too_large",
+            Self::Core(err) => err.code(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+impl fmt::Display for DatabaseOutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ERR {} ", self.code())?;
+
+        match self {
+            Self::InvalidInput(reason) => write!(f, "{}", reason),
+            Self::CmdNotAvailable => write!(f, "The command is not available over TCP."),
+            Self::FrameTooLarge { size, max } => {
+                write!(f, "The {} byte frame is over the {} byte limit.", size, max)
+            }
+            Self::PayloadTooLarge { size, max } => write!(
+                f,
+                "The {} byte frame is too far over the {} byte limit, closing the session.",
+                size, max
+            ),
+            Self::Core(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<CoreError> for DatabaseOutputError {
+    fn from(value: CoreError) -> Self {
+        Self::Core(value)
+    }
+}
+
+impl DatabaseInputType {
+    /// Permission a session's role needs to run the command, none for commands anyone may run.
+    pub fn required_permission(&self) -> Option<Permission> {
         match self {
-            Self::InvalidInput => "ERR invalid_input\n",
-            Self::CmdNotAvailable => "ERR cmd_not_available",
+            Self::Noop | Self::Quit => None,
+            Self::CollectionsList
+            | Self::Collection(_)
+            | Self::CollectionRecords(..)
+            | Self::IdRecord(..)
+            | Self::FindRecords(..)
+            | Self::ListIndexes(_) => Some(Permission::Read),
+            Self::CreateRecord(..)
+            | Self::CreateRecords(..)
+            | Self::UpdateRecord(..)
+            | Self::ReplaceRecord(..)
+            | Self::UpdateRecords(..)
+            | Self::DeleteRecords(..)
+            | Self::DeleteRecord(..) => Some(Permission::Write),
+            Self::CreateCollection(_) | Self::DeleteCollection(_) => {
+                Some(Permission::ManageCollections)
+            }
+            Self::CreateIndex(..) | Self::DropIndex(..) => Some(Permission::ManageIndexes),
+            Self::Stop | Self::Compact(_) => Some(Permission::Maintenance),
+            Self::CreateUser(..)
+            | Self::DropUser(_)
+            | Self::ChangePassword(..)
+            | Self::ListUsers
+            | Self::CreateRole(..)
+            | Self::DropRole(_)
+            | Self::ListRoles => Some(Permission::ManageUsers),
         }
     }
 }
@@ -133,16 +344,48 @@ impl DatabaseOutputMsg {
     pub fn to_bytes(&self) -> Vec<u8> {
         match self {
             Self::Noop => Vec::new(),
-            Self::Err(err) => err.as_str().as_bytes().to_vec(),
+            Self::Bye => b"BYE".to_vec(),
+            Self::Err(err) => err.to_bytes(),
             Self::Collections(collection) => collection.as_bytes().to_vec(),
             Self::Collection(collection_name) => collection_name.as_bytes().to_vec(),
             Self::Records(records) => records.as_bytes().to_vec(),
             Self::CreatedCollection(collection_id) => collection_id.as_bytes().to_vec(),
             Self::CreatedRecord(record_id) => record_id.as_bytes().to_vec(),
+            Self::CreatedRecords(outcome) => outcome.as_bytes().to_vec(),
+            Self::UpdatedRecord(record) => record.as_bytes().to_vec(),
             Self::DeletedCollection(collection_id) => collection_id.as_bytes().to_vec(),
             Self::DeletedRecord(record_id) => record_id.as_bytes().to_vec(),
+            Self::UpdateResult(outcome) => outcome.as_bytes().to_vec(),
+            Self::DeleteResult(outcome) => outcome.as_bytes().to_vec(),
+            Self::Indexes(indexes) => indexes.as_bytes().to_vec(),
+            Self::CreatedIndex(name) => name.as_bytes().to_vec(),
+            Self::DroppedIndex(name) => name.as_bytes().to_vec(),
+            Self::CreatedUser(username) => username.as_bytes().to_vec(),
+            Self::DroppedUser(username) => username.as_bytes().to_vec(),
+            Self::ChangedPassword(username) => username.as_bytes().to_vec(),
+            Self::Users(users) => users.as_bytes().to_vec(),
+            Self::CreatedRole(name) => name.as_bytes().to_vec(),
+            Self::DroppedRole(name) => name.as_bytes().to_vec(),
+            Self::Roles(roles) => roles.as_bytes().to_vec(),
         }
     }
+
+    /// Same as [`Self::to_bytes`], but prefixed with `#<tag>` when the request carried a tag.
+    pub fn to_tagged_bytes(&self, tag: Option<&str>) -> Vec<u8> {
+        let Some(tag) = tag else {
+            return self.to_bytes();
+        };
+
+        let body = self.to_bytes();
+        let mut bytes = format!("#{}", tag).into_bytes();
+
+        if !body.is_empty() {
+            bytes.push(b' ');
+            bytes.extend(body);
+        }
+
+        bytes
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -160,13 +403,122 @@ impl InputSource {
     }
 }
 
+/// Splits an optional leading `#<tag>` off a request, returning the tag and the rest of the
+/// request. Tags are opaque to the database and only echoed back on the response.
+///
+/// Requests with an overlong tag are rejected, but the tag is still returned truncated to
+/// [`MOLECULE_MAX_REQUEST_TAG_LEN`], so the error can be matched to the request.
+pub fn split_request_tag(value: &str) -> (Option<String>, Result<&str>) {
+    let Some(tagged) = value.strip_prefix('#') else {
+        return (None, Ok(value));
+    };
+
+    let (tag, rest) = tagged
+        .split_once(char::is_whitespace)
+        .unwrap_or((tagged, ""));
+
+    if tag.is_empty() || tag.len() > MOLECULE_MAX_REQUEST_TAG_LEN {
+        let truncated_len = tag
+            .char_indices()
+            .map(|(index, char)| index + char.len_utf8())
+            .take_while(|&end| end <= MOLECULE_MAX_REQUEST_TAG_LEN)
+            .last()
+            .unwrap_or(0);
+        let truncated = &tag[..truncated_len];
+
+        return (
+            (!truncated.is_empty()).then(|| truncated.to_string()),
+            Err(anyhow!(
+                "Request tags must be between 1 and {} characters long.",
+                MOLECULE_MAX_REQUEST_TAG_LEN
+            )),
+        );
+    }
+
+    (Some(tag.to_string()), Ok(rest.trim_start()))
+}
+
+/// Splits off the first `count` whitespace separated arguments, returning them along with the
+/// untouched remainder of the input (used for JSON payloads, whose whitespace must survive).
+fn split_args(value: &str, count: usize) -> (Vec<&str>, &str) {
+    let mut args = Vec::with_capacity(count);
+    let mut rest = value.trim_start();
+
+    while args.len() < count && !rest.is_empty() {
+        let (arg, remainder) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        args.push(arg);
+        rest = remainder.trim_start();
+    }
+
+    (args, rest)
+}
+
+/// Parses a run of whitespace separated JSON values, such as `{"age": 30} {"limit": 1}`.
+fn parse_json_args(value: &str) -> Result<Vec<Value>> {
+    Ok(serde_json::Deserializer::from_str(value)
+        .into_iter::<Value>()
+        .collect::<Result<_, _>>()?)
+}
+
+/// Every database command accepted over TCP, advertised to clients during the handshake.
+pub const TCP_COMMANDS: &[&str] = &[
+    "QUIT",
+    "COLLECTIONS_LIST",
+    "COLLECTION",
+    "CLN_GET",
+    "REC_GET",
+    "FIND",
+    "CLN_CREATE",
+    "REC_CREATE",
+    "REC_CREATE_MANY",
+    "REC_UPDATE",
+    "REC_REPLACE",
+    "UPDATE_MANY",
+    "DELETE_MANY",
+    "INDEX_CREATE",
+    "INDEX_DROP",
+    "INDEX_LIST",
+    "CLN_DELETE",
+    "REC_DELETE",
+    "USER_CREATE",
+    "USER_DROP",
+    "USER_PASSWD",
+    "USER_LIST",
+    "ROLE_CREATE",
+    "ROLE_DROP",
+    "ROLE_LIST",
+];
+
+/// The request as it's safe to log, with the password of user commands masked.
+pub fn redact_request(request: &str) -> String {
+    let mut parts: Vec<&str> = request.split_whitespace().collect();
+    let command_at = match parts.first() {
+        Some(tag) if tag.starts_with('#') => 1,
+        _ => 0,
+    };
+    let password_at = match parts.get(command_at) {
+        Some(&"USER_CREATE") | Some(&"USER_PASSWD") => command_at + 2,
+        _ => return request.to_string(),
+    };
+
+    if let Some(password) = parts.get_mut(password_at) {
+        *password = "***";
+    }
+
+    parts.join(" ")
+}
+
 pub fn parse_str_to_db_input_type(value: String, source: InputSource) -> Result<DatabaseInputType> {
     if value == "STOP" && source != InputSource::Tcp {
         return Ok(DatabaseInputType::Stop);
     }
 
+    if value == "QUIT" && source == InputSource::Tcp {
+        return Ok(DatabaseInputType::Quit);
+    }
+
     let parts: Vec<&str> = value.split_whitespace().collect();
-    let command = *match parts.get(0) {
+    let command = *match parts.first() {
         Some(cmd) => cmd,
         None => return Ok(DatabaseInputType::Noop),
     };
@@ -181,13 +533,22 @@ pub fn parse_str_to_db_input_type(value: String, source: InputSource) -> Result<
             bail!("Input type COLLECTION is missing required argument for collection_id.");
         }
         "CLN_GET" => {
-            if let Some(collection_id) = parts.get(1) {
+            let (args, rest) = split_args(&value, 2);
+            let json_args = parse_json_args(rest)?;
+
+            if let (Some(collection_id), [] | [_]) = (args.get(1), json_args.as_slice()) {
+                let options = match json_args.first() {
+                    Some(options) => QueryOptions::parse(options)?,
+                    None => QueryOptions::default(),
+                };
+
                 return Ok(DatabaseInputType::CollectionRecords(
                     collection_id.to_string(),
+                    options,
                 ));
             }
 
-            bail!("Input type CLN_GET is missing required argument for collection_id.");
+            bail!("Input type CLN_GET expects arguments for collection_id and optionally options.");
         }
         "REC_GET" => {
             if let (Some(collection_id), Some(record_id)) = (parts.get(1), parts.get(2)) {
@@ -199,6 +560,30 @@ pub fn parse_str_to_db_input_type(value: String, source: InputSource) -> Result<
 
             bail!("Input type REC_GET is missing required argument for collection_id, record_id.");
         }
+        "FIND" => {
+            let (args, rest) = split_args(&value, 2);
+            let json_args = parse_json_args(rest)?;
+
+            if let (Some(collection_id), [filter, options @ ..]) =
+                (args.get(1), json_args.as_slice())
+                && options.len() <= 1
+            {
+                let options = match options.first() {
+                    Some(options) => QueryOptions::parse(options)?,
+                    None => QueryOptions::default(),
+                };
+
+                return Ok(DatabaseInputType::FindRecords(
+                    collection_id.to_string(),
+                    Filter::parse(filter)?,
+                    options,
+                ));
+            }
+
+            bail!(
+                "Input type FIND expects arguments for collection_id, filter and optionally options."
+            );
+        }
         "CLN_CREATE" => {
             if let Some(name) = parts.get(1) {
                 return Ok(DatabaseInputType::CreateCollection(name.to_string()));
@@ -207,10 +592,12 @@ pub fn parse_str_to_db_input_type(value: String, source: InputSource) -> Result<
             bail!("Input type CLN_CREATE is missing required argument for name.");
         }
         "REC_CREATE" => {
-            if let (Some(collection_id), content) = (parts.get(1), parts[2..].join(" ")) {
+            let (args, content) = split_args(&value, 2);
+
+            if let (Some(collection_id), false) = (args.get(1), content.is_empty()) {
                 return Ok(DatabaseInputType::CreateRecord(
                     collection_id.to_string(),
-                    serde_json::from_str(&content)?,
+                    serde_json::from_str(content)?,
                 ));
             }
 
@@ -218,6 +605,137 @@ pub fn parse_str_to_db_input_type(value: String, source: InputSource) -> Result<
                 "Input type REC_CREATE is missing required argument for collection_id, contents."
             );
         }
+        "REC_CREATE_MANY" => {
+            let (args, rest) = split_args(&value, 2);
+            let json_args = parse_json_args(rest)?;
+
+            if let (Some(collection_id), [contents, options @ ..]) =
+                (args.get(1), json_args.as_slice())
+                && options.len() <= 1
+            {
+                let options = match options.first() {
+                    Some(options) => serde_json::from_value(options.clone())?,
+                    None => InsertOptions::default(),
+                };
+
+                return Ok(DatabaseInputType::CreateRecords(
+                    collection_id.to_string(),
+                    serde_json::from_value(contents.clone())?,
+                    options,
+                ));
+            }
+
+            bail!(
+                "Input type REC_CREATE_MANY expects arguments for collection_id, contents and optionally options."
+            );
+        }
+        "REC_UPDATE" | "REC_REPLACE" => {
+            let (args, content) = split_args(&value, 3);
+
+            if let (Some(collection_id), Some(record_id), false) =
+                (args.get(1), args.get(2), content.is_empty())
+            {
+                let (collection_id, record_id) = (collection_id.to_string(), record_id.to_string());
+
+                if command == "REC_REPLACE" {
+                    return Ok(DatabaseInputType::ReplaceRecord(
+                        collection_id,
+                        record_id,
+                        serde_json::from_str(content)?,
+                    ));
+                }
+
+                let update: Value = serde_json::from_str(content)?;
+                return Ok(DatabaseInputType::UpdateRecord(
+                    collection_id,
+                    record_id,
+                    Update::parse(&update)?,
+                ));
+            }
+
+            bail!(
+                "Input type {} is missing required argument for collection_id, record_id, contents.",
+                command
+            );
+        }
+        "UPDATE_MANY" => {
+            let (args, rest) = split_args(&value, 2);
+            let json_args = parse_json_args(rest)?;
+
+            if let (Some(collection_id), [filter, update, options @ ..]) =
+                (args.get(1), json_args.as_slice())
+                && options.len() <= 1
+            {
+                let options = match options.first() {
+                    Some(options) => serde_json::from_value(options.clone())?,
+                    None => UpdateOptions::default(),
+                };
+
+                return Ok(DatabaseInputType::UpdateRecords(
+                    collection_id.to_string(),
+                    Filter::parse(filter)?,
+                    Update::parse(update)?,
+                    options,
+                ));
+            }
+
+            bail!(
+                "Input type UPDATE_MANY expects arguments for collection_id, filter, update and optionally options."
+            );
+        }
+        "DELETE_MANY" => {
+            let (args, rest) = split_args(&value, 2);
+            let json_args = parse_json_args(rest)?;
+
+            if let (Some(collection_id), [filter]) = (args.get(1), json_args.as_slice()) {
+                return Ok(DatabaseInputType::DeleteRecords(
+                    collection_id.to_string(),
+                    Filter::parse(filter)?,
+                ));
+            }
+
+            bail!("Input type DELETE_MANY is missing required argument for collection_id, filter.");
+        }
+        "INDEX_CREATE" => {
+            let (args, rest) = split_args(&value, 3);
+            let json_args = parse_json_args(rest)?;
+
+            if let (Some(collection_id), Some(fields), [] | [_]) =
+                (args.get(1), args.get(2), json_args.as_slice())
+            {
+                let options = match json_args.first() {
+                    Some(options) => serde_json::from_value(options.clone())?,
+                    None => IndexOptions::default(),
+                };
+
+                return Ok(DatabaseInputType::CreateIndex(
+                    collection_id.to_string(),
+                    fields.split(',').map(str::to_string).collect(),
+                    options,
+                ));
+            }
+
+            bail!(
+                "Input type INDEX_CREATE expects arguments for collection_id, fields and optionally options."
+            );
+        }
+        "INDEX_DROP" => {
+            if let (Some(collection_id), Some(name)) = (parts.get(1), parts.get(2)) {
+                return Ok(DatabaseInputType::DropIndex(
+                    collection_id.to_string(),
+                    name.to_string(),
+                ));
+            }
+
+            bail!("Input type INDEX_DROP is missing required argument for collection_id, name.");
+        }
+        "INDEX_LIST" => {
+            if let Some(collection_id) = parts.get(1) {
+                return Ok(DatabaseInputType::ListIndexes(collection_id.to_string()));
+            }
+
+            bail!("Input type INDEX_LIST is missing required argument for collection_id.");
+        }
         "CLN_DELETE" => {
             if let Some(collection_id) = parts.get(1) {
                 return Ok(DatabaseInputType::DeleteCollection(
@@ -239,6 +757,66 @@ pub fn parse_str_to_db_input_type(value: String, source: InputSource) -> Result<
                 "Input type REC_DELETE is missing required argument for collection_id, record_id."
             );
         }
+        "COMPACT" if source != InputSource::Tcp => {
+            if let Some(collection_id) = parts.get(1) {
+                return Ok(DatabaseInputType::Compact(collection_id.to_string()));
+            }
+
+            bail!("Input type COMPACT is missing required argument for collection_id.");
+        }
+        "USER_CREATE" => {
+            if let (Some(username), Some(password), Some(role)) =
+                (parts.get(1), parts.get(2), parts.get(3))
+            {
+                return Ok(DatabaseInputType::CreateUser(
+                    username.to_string(),
+                    password.to_string(),
+                    role.to_string(),
+                ));
+            }
+
+            bail!(
+                "Input type USER_CREATE is missing required argument for username, password, role."
+            );
+        }
+        "USER_DROP" => {
+            if let Some(username) = parts.get(1) {
+                return Ok(DatabaseInputType::DropUser(username.to_string()));
+            }
+
+            bail!("Input type USER_DROP is missing required argument for username.");
+        }
+        "USER_PASSWD" => {
+            if let (Some(username), Some(password)) = (parts.get(1), parts.get(2)) {
+                return Ok(DatabaseInputType::ChangePassword(
+                    username.to_string(),
+                    password.to_string(),
+                ));
+            }
+
+            bail!("Input type USER_PASSWD is missing required argument for username, password.");
+        }
+        "USER_LIST" => Ok(DatabaseInputType::ListUsers),
+        "ROLE_CREATE" => {
+            if let (Some(name), Some(permissions)) = (parts.get(1), parts.get(2)) {
+                let permissions = permissions
+                    .split(',')
+                    .map(Permission::parse)
+                    .collect::<Result<Vec<_>>>()?;
+
+                return Ok(DatabaseInputType::CreateRole(name.to_string(), permissions));
+            }
+
+            bail!("Input type ROLE_CREATE is missing required argument for name, permissions.");
+        }
+        "ROLE_DROP" => {
+            if let Some(name) = parts.get(1) {
+                return Ok(DatabaseInputType::DropRole(name.to_string()));
+            }
+
+            bail!("Input type ROLE_DROP is missing required argument for name.");
+        }
+        "ROLE_LIST" => Ok(DatabaseInputType::ListRoles),
         _ => bail!(
             "Invalid or unsupported input type for {}: {}",
             source.as_str(),
@@ -246,3 +824,70 @@ pub fn parse_str_to_db_input_type(value: String, source: InputSource) -> Result<
         ),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supports_versions_between_min_and_current() {
+        let capabilities = ServerCapabilities::new(1024, false);
+
+        assert!(capabilities.supports_version(MOLECULE_MIN_PROTOCOL_VERSION));
+        assert!(capabilities.supports_version(MOLECULE_PROTOCOL_VERSION));
+        assert!(!capabilities.supports_version(MOLECULE_MIN_PROTOCOL_VERSION - 1));
+        assert!(!capabilities.supports_version(MOLECULE_PROTOCOL_VERSION + 1));
+        assert!(
+            String::from_utf8(HandShakeOutputMsg::InitConn(capabilities).to_bytes())
+                .unwrap()
+                .starts_with(r#"INITCONN {"version":"#)
+        );
+    }
+
+    #[test]
+    fn splits_request_tags() {
+        let (tag, request) = split_request_tag("#42 CLN_CREATE users");
+        assert_eq!(tag.as_deref(), Some("42"));
+        assert_eq!(request.unwrap(), "CLN_CREATE users");
+
+        let (tag, request) = split_request_tag("CLN_CREATE users");
+        assert_eq!(tag, None);
+        assert_eq!(request.unwrap(), "CLN_CREATE users");
+
+        let (tag, request) = split_request_tag("#only-a-tag");
+        assert_eq!(tag.as_deref(), Some("only-a-tag"));
+        assert_eq!(request.unwrap(), "");
+
+        let (tag, request) = split_request_tag("# COLLECTIONS_LIST");
+        assert_eq!(tag, None);
+        assert!(request.is_err());
+    }
+
+    #[test]
+    fn truncates_overlong_request_tags() {
+        let tag = "x".repeat(MOLECULE_MAX_REQUEST_TAG_LEN + 1);
+        let request = format!("#{} COLLECTIONS_LIST", tag);
+        let (truncated, request) = split_request_tag(&request);
+
+        assert_eq!(truncated.unwrap(), tag[..MOLECULE_MAX_REQUEST_TAG_LEN]);
+        assert!(request.is_err());
+
+        // Multi-byte characters are never split.
+        let tag = format!("{}é", "x".repeat(MOLECULE_MAX_REQUEST_TAG_LEN - 1));
+        let (truncated, _) = split_request_tag(&format!("#{} COLLECTIONS_LIST", tag));
+
+        assert_eq!(
+            truncated.unwrap(),
+            "x".repeat(MOLECULE_MAX_REQUEST_TAG_LEN - 1)
+        );
+    }
+
+    #[test]
+    fn tags_responses() {
+        let created = DatabaseOutputMsg::CreatedCollection("id".into());
+
+        assert_eq!(created.to_tagged_bytes(None), b"id");
+        assert_eq!(created.to_tagged_bytes(Some("42")), b"#42 id");
+        assert_eq!(DatabaseOutputMsg::Noop.to_tagged_bytes(Some("42")), b"#42");
+    }
+}
@@ -0,0 +1,27 @@
+use std::net::SocketAddr;
+
+use crate::constants::MOLECULE_MIN_PROTOCOL_VERSION;
+
+/// State tracked for a single TCP connection for as long as it stays open.
+#[derive(Debug)]
+pub struct Session {
+    /// Address of the connected client.
+    pub peer_addr: SocketAddr,
+    /// Username the client authenticated with during the handshake, if any.
+    pub username: Option<String>,
+    /// Protocol version agreed on during the handshake, which newer features are gated on.
+    pub protocol_version: u32,
+    /// Number of database commands answered on this session.
+    pub commands_handled: u64,
+}
+
+impl Session {
+    pub fn new(peer_addr: SocketAddr) -> Self {
+        Self {
+            peer_addr,
+            username: None,
+            protocol_version: MOLECULE_MIN_PROTOCOL_VERSION,
+            commands_handled: 0,
+        }
+    }
+}
@@ -1,14 +1,23 @@
 use std::sync::Arc;
 
 use anyhow::Result;
-use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::io::BufWriter;
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
 
 use crate::auth::MoleculeAuthApi;
+use crate::constants::MOLECULE_MAX_HANDSHAKE_FRAME_SIZE;
+use crate::constants::MOLECULE_MIN_PROTOCOL_VERSION;
 use crate::core::collection::MoleculeCoreCollectionApi;
+use crate::core::error::CoreError;
+use crate::core::index::MoleculeCoreIndexApi;
 use crate::core::record::MoleculeCoreRecordsApi;
+use crate::frame::FrameError;
+use crate::frame::has_buffered_frame;
+use crate::frame::read_frame;
+use crate::frame::write_frame;
 use crate::molecule::Molecule;
 use crate::proto::DatabaseInputType;
 use crate::proto::DatabaseOutputError;
@@ -17,22 +26,33 @@ use crate::proto::HandShakeInputMsg;
 use crate::proto::HandShakeOutputError;
 use crate::proto::HandShakeOutputMsg;
 use crate::proto::InputSource;
+use crate::proto::ServerCapabilities;
 use crate::proto::parse_str_to_db_input_type;
+use crate::proto::redact_request;
+use crate::proto::split_request_tag;
+use crate::session::Session;
+
+/// Client connection with buffered reads, so pipelined frames can be detected, and buffered
+/// writes, so responses to a pipelined batch go out together.
+type ClientStream = BufReader<BufWriter<TcpStream>>;
 
 trait MoleculeTcpHandle {
-    async fn handshake(&self, client: &mut TcpStream) -> Result<()>;
-    async fn handle_client(&self, client: &mut TcpStream) -> Result<()>;
+    async fn handshake(&self, client: &mut ClientStream, session: &mut Session) -> Result<bool>;
+    async fn handle_client(&self, client: &mut ClientStream, session: &mut Session) -> Result<()>;
+    async fn handle_request(&self, session: &mut Session, request: &str) -> DatabaseOutputMsg;
+    async fn handle_command(
+        &self,
+        session: &mut Session,
+        input: DatabaseInputType,
+    ) -> Result<DatabaseOutputMsg>;
 }
 
 trait MoleculeTcpExt {
-    async fn write_db_err(
-        &self,
-        client: &mut TcpStream,
-        database_output_error: DatabaseOutputError,
-    ) -> Result<()>;
+    async fn read_request(&self, client: &mut ClientStream) -> Result<Option<Vec<u8>>, FrameError>;
+    async fn write_response(&self, client: &mut ClientStream, payload: &[u8]) -> Result<()>;
     async fn write_handshake_err(
         &self,
-        client: &mut TcpStream,
+        client: &mut ClientStream,
         handshake_output_error: HandShakeOutputError,
     ) -> Result<()>;
 }
@@ -47,13 +67,16 @@ impl MoleculeTcpApi for Molecule {
         let tcp_listener = TcpListener::bind(&bind_addr).await?;
 
         loop {
-            let (mut stream, socket) = tcp_listener.accept().await?;
+            let (stream, socket) = tcp_listener.accept().await?;
             log::info!("Client connected with IP: {}", socket.ip());
 
             let this = self.clone();
             tokio::spawn(async move {
-                if let Err(e) = this.handle_client(&mut stream).await {
-                    eprintln!("Client error: {}", e.to_string());
+                let mut client = BufReader::new(BufWriter::new(stream));
+                let mut session = Session::new(socket);
+
+                if let Err(e) = this.handle_client(&mut client, &mut session).await {
+                    eprintln!("Client error: {}", e);
                 }
             });
         }
@@ -61,71 +84,237 @@ impl MoleculeTcpApi for Molecule {
 }
 
 impl MoleculeTcpHandle for Molecule {
-    async fn handshake(&self, client: &mut TcpStream) -> Result<()> {
-        client
-            .write_all(HandShakeOutputMsg::InitConn.into())
-            .await?;
+    /// Runs the handshake with a freshly connected client, returning whether it completed
+    /// successfully and the session can start accepting database commands.
+    ///
+    /// Clients answer `INITCONN` with `OK`, speaking protocol version 1, or with `HELLO` and one
+    /// of the advertised versions, which the session then speaks.
+    async fn handshake(&self, client: &mut ClientStream, session: &mut Session) -> Result<bool> {
+        let auth_required = self.auth_required().await;
+        let capabilities = ServerCapabilities::new(self.max_frame_size, auth_required);
+        self.write_response(
+            client,
+            &HandShakeOutputMsg::InitConn(capabilities.clone()).to_bytes(),
+        )
+        .await?;
+        client.flush().await?;
 
-        let mut buf = vec![0u8; 1024];
-        let size = client.read(&mut buf).await?;
-
-        let incoming = String::from_utf8_lossy(&buf[..size]).trim().to_string();
-        log::info!("Handshake: {}", incoming);
+        // Nothing but a short handshake message is accepted before the client authenticates.
+        let buf = match read_frame(client, MOLECULE_MAX_HANDSHAKE_FRAME_SIZE).await {
+            Ok(Some(buf)) => buf,
+            Ok(None) => return Ok(false),
+            Err(FrameError::TooLarge { .. }) => {
+                self.write_handshake_err(client, HandShakeOutputError::FrameTooLarge)
+                    .await?;
+                return Ok(false);
+            }
+            Err(FrameError::PayloadTooLarge { .. }) => {
+                self.write_handshake_err(client, HandShakeOutputError::PayloadTooLarge)
+                    .await?;
+                return Ok(false);
+            }
+            Err(err) => return Err(err.into()),
+        };
 
+        let incoming = String::from_utf8_lossy(&buf).trim().to_string();
         let incoming_parts: Vec<&str> = incoming.split_whitespace().collect();
-        let Some(&raw_message) = incoming_parts.get(0) else {
+        let Some(&raw_message) = incoming_parts.first() else {
             self.write_handshake_err(client, HandShakeOutputError::MalformedRequest)
                 .await?;
-            return Ok(());
+            return Ok(false);
         };
+        // The rest of the message may hold a password.
+        log::info!("Handshake: {}", raw_message);
         let message = match HandShakeInputMsg::try_from(raw_message) {
             Ok(parsed_msg) => parsed_msg,
-            Err(err) => return Ok(self.write_handshake_err(client, err).await?),
+            Err(err) => {
+                self.write_handshake_err(client, err).await?;
+                return Ok(false);
+            }
+        };
+
+        let (version, auth_str) = match message {
+            HandShakeInputMsg::Ok => (MOLECULE_MIN_PROTOCOL_VERSION, incoming_parts.get(1)),
+            HandShakeInputMsg::Hello => {
+                let Some(raw_version) = incoming_parts.get(1) else {
+                    self.write_handshake_err(client, HandShakeOutputError::InvalidHandShake)
+                        .await?;
+                    return Ok(false);
+                };
+                let Ok(version) = raw_version.parse::<u32>() else {
+                    self.write_handshake_err(client, HandShakeOutputError::MalformedRequest)
+                        .await?;
+                    return Ok(false);
+                };
+
+                (version, incoming_parts.get(2))
+            }
         };
 
-        if let Some(auth_str) = incoming_parts.get(1) {
+        if !capabilities.supports_version(version) {
+            self.write_handshake_err(client, HandShakeOutputError::UnsupportedVersion)
+                .await?;
+            return Ok(false);
+        }
+
+        if auth_str.is_none() && auth_required {
+            self.write_handshake_err(client, HandShakeOutputError::AuthRequired)
+                .await?;
+            return Ok(false);
+        }
+
+        if let Some(auth_str) = auth_str {
             let Some((username, password)) = auth_str.split_once(":") else {
-                return Ok(self
-                    .write_handshake_err(client, HandShakeOutputError::MalformedAuthStr)
-                    .await?);
+                self.write_handshake_err(client, HandShakeOutputError::MalformedAuthStr)
+                    .await?;
+                return Ok(false);
             };
 
             if !self.is_valid_user(username, password).await? {
-                return Ok(self
-                    .write_handshake_err(client, HandShakeOutputError::IncorrectAuthInfo)
-                    .await?);
+                self.write_handshake_err(client, HandShakeOutputError::IncorrectAuthInfo)
+                    .await?;
+                return Ok(false);
             };
 
-            log::info!("Client authed with username: {}", username)
+            log::info!("Client authed with username: {}", username);
+            session.username = Some(username.to_owned());
         }
 
-        if message != HandShakeInputMsg::Ok {
-            return Ok(self
-                .write_handshake_err(client, HandShakeOutputError::InvalidHandShake)
-                .await?);
-        }
+        session.protocol_version = version;
+        log::info!(
+            "Client {} speaks protocol version {}.",
+            session.peer_addr,
+            session.protocol_version
+        );
 
-        client.write_all(HandShakeOutputMsg::Ready.into()).await?;
-        Ok(())
+        let ready = match message {
+            HandShakeInputMsg::Ok => HandShakeOutputMsg::Ready(None),
+            HandShakeInputMsg::Hello => HandShakeOutputMsg::Ready(Some(version)),
+        };
+        self.write_response(client, &ready.to_bytes()).await?;
+        client.flush().await?;
+        Ok(true)
     }
 
-    async fn handle_client(&self, client: &mut TcpStream) -> Result<()> {
-        self.handshake(client).await?;
-        let mut buf: Vec<u8> = vec![0u8; 1024];
-        let size = client.read(&mut buf).await?;
+    /// Keeps answering database commands on an authenticated session until the client
+    /// sends `QUIT` or closes the connection.
+    ///
+    /// Requests are answered strictly in the order they arrive. Responses are only flushed once
+    /// no further complete request is already buffered, so a pipelined batch is answered with as
+    /// few writes as possible.
+    async fn handle_client(&self, client: &mut ClientStream, session: &mut Session) -> Result<()> {
+        if !self.handshake(client, session).await? {
+            return Ok(());
+        }
+
+        loop {
+            let (tag, response) = match self.read_request(client).await {
+                Ok(Some(buf)) => {
+                    let incoming = String::from_utf8_lossy(&buf).trim().to_string();
+                    log::info!("Database command: {}", redact_request(&incoming));
+
+                    let (tag, request) = split_request_tag(&incoming);
+                    let response = match request {
+                        Ok(request) => self.handle_request(session, request).await,
+                        Err(err) => DatabaseOutputMsg::Err(DatabaseOutputError::InvalidInput(
+                            err.to_string(),
+                        )),
+                    };
+
+                    (tag, response)
+                }
+                Ok(None) => {
+                    client.flush().await?;
+                    log::info!(
+                        "Client {} disconnected after {} command(s).",
+                        session.peer_addr,
+                        session.commands_handled
+                    );
+                    return Ok(());
+                }
+                Err(FrameError::TooLarge { size, max }) => {
+                    log::warn!(
+                        "Client {} sent a {} byte frame, over the {} byte limit.",
+                        session.peer_addr,
+                        size,
+                        max
+                    );
+                    (
+                        None,
+                        DatabaseOutputMsg::Err(DatabaseOutputError::FrameTooLarge { size, max }),
+                    )
+                }
+                Err(FrameError::PayloadTooLarge { size, max }) => {
+                    log::warn!(
+                        "Client {} sent a {} byte frame, too far over the {} byte limit to discard.",
+                        session.peer_addr,
+                        size,
+                        max
+                    );
+                    let response =
+                        DatabaseOutputMsg::Err(DatabaseOutputError::PayloadTooLarge { size, max });
+
+                    self.write_response(client, &response.to_bytes()).await?;
+                    client.flush().await?;
+                    return Ok(());
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            if let DatabaseOutputMsg::Err(err) = &response {
+                log::error!("Database error: {}", err);
+            }
+
+            self.write_response(client, &response.to_tagged_bytes(tag.as_deref()))
+                .await?;
+
+            if response == DatabaseOutputMsg::Bye {
+                client.flush().await?;
+                log::info!(
+                    "Client {} quit after {} command(s).",
+                    session.peer_addr,
+                    session.commands_handled
+                );
+                return Ok(());
+            }
 
-        let incoming_db_cmd = String::from_utf8_lossy(&buf[..size]).trim().to_string();
-        log::info!("Database command: {}", incoming_db_cmd);
+            if !has_buffered_frame(client.buffer()) {
+                client.flush().await?;
+            }
+        }
+    }
 
-        let input = match parse_str_to_db_input_type(incoming_db_cmd, InputSource::Tcp) {
+    /// Parses and runs a single request once its tag has been split off. Every failure is
+    /// turned into an error response, so a failed request never ends the session.
+    async fn handle_request(&self, session: &mut Session, request: &str) -> DatabaseOutputMsg {
+        let input = match parse_str_to_db_input_type(request.to_string(), InputSource::Tcp) {
             Ok(parsed) => parsed,
-            Err(_) => {
-                return Ok(self
-                    .write_db_err(client, DatabaseOutputError::InvalidInput)
-                    .await?);
+            Err(err) => {
+                return DatabaseOutputMsg::Err(DatabaseOutputError::InvalidInput(err.to_string()));
             }
         };
 
+        if input == DatabaseInputType::Quit {
+            return DatabaseOutputMsg::Bye;
+        }
+
+        session.commands_handled += 1;
+
+        match self.handle_command(session, input).await {
+            Ok(response) => response,
+            Err(err) => DatabaseOutputMsg::Err(CoreError::from(&err).into()),
+        }
+    }
+
+    /// Runs a command the session's role allows.
+    async fn handle_command(
+        &self,
+        session: &mut Session,
+        input: DatabaseInputType,
+    ) -> Result<DatabaseOutputMsg> {
+        self.authorize(session.username.as_deref(), InputSource::Tcp, &input)
+            .await?;
+
         let response = match input {
             DatabaseInputType::CollectionsList => {
                 let collections = self.list_collections().await?;
@@ -138,8 +327,14 @@ impl MoleculeTcpHandle for Molecule {
 
                 DatabaseOutputMsg::Collection(collection.unwrap_or("null".into()))
             }
-            DatabaseInputType::CollectionRecords(collection_id) => {
-                let records = self.get_records(collection_id).await?;
+            DatabaseInputType::CollectionRecords(collection_id, options) => {
+                let records = self.get_records(collection_id, options).await?;
+                let json_str = serde_json::to_string(&records)?;
+
+                DatabaseOutputMsg::Records(json_str)
+            }
+            DatabaseInputType::FindRecords(collection_id, filter, options) => {
+                let records = self.find_records(collection_id, filter, options).await?;
                 let json_str = serde_json::to_string(&records)?;
 
                 DatabaseOutputMsg::Records(json_str)
@@ -158,48 +353,272 @@ impl MoleculeTcpHandle for Molecule {
                 let record_id = self.create_record(collection_id, contents).await?;
                 DatabaseOutputMsg::CreatedRecord(record_id)
             }
-            DatabaseInputType::DeleteCollection(name) => {
-                let collection_id = self.delete_collection(name).await?;
-                DatabaseOutputMsg::DeletedCollection(collection_id)
+            DatabaseInputType::CreateRecords(collection_id, contents, options) => {
+                let outcome = self
+                    .create_records(collection_id, contents, options)
+                    .await?;
+
+                DatabaseOutputMsg::CreatedRecords(serde_json::to_string(&outcome)?)
+            }
+            DatabaseInputType::UpdateRecord(collection_id, record_id, update) => {
+                match self
+                    .update_record(collection_id, record_id.clone(), update)
+                    .await?
+                {
+                    Some(record) => {
+                        DatabaseOutputMsg::UpdatedRecord(serde_json::to_string(&record)?)
+                    }
+                    None => DatabaseOutputMsg::Err(CoreError::RecordNotFound(record_id).into()),
+                }
+            }
+            DatabaseInputType::ReplaceRecord(collection_id, record_id, contents) => {
+                match self
+                    .replace_record(collection_id, record_id.clone(), contents)
+                    .await?
+                {
+                    Some(record) => {
+                        DatabaseOutputMsg::UpdatedRecord(serde_json::to_string(&record)?)
+                    }
+                    None => DatabaseOutputMsg::Err(CoreError::RecordNotFound(record_id).into()),
+                }
+            }
+            DatabaseInputType::UpdateRecords(collection_id, filter, update, options) => {
+                let outcome = self
+                    .update_records(collection_id, filter, update, options)
+                    .await?;
+
+                DatabaseOutputMsg::UpdateResult(serde_json::to_string(&outcome)?)
+            }
+            DatabaseInputType::DeleteRecords(collection_id, filter) => {
+                let outcome = self.delete_records(collection_id, filter).await?;
+
+                DatabaseOutputMsg::DeleteResult(serde_json::to_string(&outcome)?)
+            }
+            DatabaseInputType::CreateIndex(collection_id, fields, options) => {
+                let name = self.create_index(collection_id, fields, options).await?;
+                DatabaseOutputMsg::CreatedIndex(name)
+            }
+            DatabaseInputType::DropIndex(collection_id, name) => {
+                if self.drop_index(collection_id, name.clone()).await? {
+                    DatabaseOutputMsg::DroppedIndex(name)
+                } else {
+                    DatabaseOutputMsg::Err(CoreError::IndexNotFound(name).into())
+                }
+            }
+            DatabaseInputType::ListIndexes(collection_id) => {
+                let indexes = self.list_indexes(collection_id).await?;
+                DatabaseOutputMsg::Indexes(serde_json::to_string(&indexes)?)
+            }
+            DatabaseInputType::DeleteCollection(collection_id) => {
+                match self.delete_collection(collection_id.clone()).await? {
+                    Some(collection_id) => DatabaseOutputMsg::DeletedCollection(collection_id),
+                    None => {
+                        DatabaseOutputMsg::Err(CoreError::CollectionNotFound(collection_id).into())
+                    }
+                }
             }
             DatabaseInputType::DeleteRecord(collection_id, record_id) => {
-                let record_id = self.delete_record(collection_id, record_id).await?;
-                DatabaseOutputMsg::DeletedRecord(record_id)
+                match self.delete_record(collection_id, record_id.clone()).await? {
+                    Some(record_id) => DatabaseOutputMsg::DeletedRecord(record_id),
+                    None => DatabaseOutputMsg::Err(CoreError::RecordNotFound(record_id).into()),
+                }
+            }
+            DatabaseInputType::CreateUser(username, password, role) => {
+                self.create_user(username.clone(), password, role).await?;
+                DatabaseOutputMsg::CreatedUser(username)
+            }
+            DatabaseInputType::DropUser(username) => {
+                self.drop_user(username.clone()).await?;
+                DatabaseOutputMsg::DroppedUser(username)
+            }
+            DatabaseInputType::ChangePassword(username, password) => {
+                self.change_password(username.clone(), password).await?;
+                DatabaseOutputMsg::ChangedPassword(username)
+            }
+            DatabaseInputType::ListUsers => {
+                DatabaseOutputMsg::Users(serde_json::to_string(&self.list_users().await)?)
+            }
+            DatabaseInputType::CreateRole(name, permissions) => {
+                self.create_role(name.clone(), permissions).await?;
+                DatabaseOutputMsg::CreatedRole(name)
+            }
+            DatabaseInputType::DropRole(name) => {
+                self.drop_role(name.clone()).await?;
+                DatabaseOutputMsg::DroppedRole(name)
+            }
+            DatabaseInputType::ListRoles => {
+                DatabaseOutputMsg::Roles(serde_json::to_string(&self.list_roles().await)?)
             }
             DatabaseInputType::Noop => DatabaseOutputMsg::Noop,
-            DatabaseInputType::Stop => DatabaseOutputMsg::Err(DatabaseOutputError::CmdNotAvailable),
+            DatabaseInputType::Stop | DatabaseInputType::Quit | DatabaseInputType::Compact(_) => {
+                DatabaseOutputMsg::Err(DatabaseOutputError::CmdNotAvailable)
+            }
         };
 
-        client.write_all(&response.to_bytes()).await?;
-
-        Ok(())
+        Ok(response)
     }
 }
 
 impl MoleculeTcpExt for Molecule {
+    #[inline]
+    async fn read_request(&self, client: &mut ClientStream) -> Result<Option<Vec<u8>>, FrameError> {
+        read_frame(client, self.max_frame_size).await
+    }
+
+    #[inline]
+    async fn write_response(&self, client: &mut ClientStream, payload: &[u8]) -> Result<()> {
+        write_frame(client, payload).await?;
+        Ok(())
+    }
+
     #[inline]
     async fn write_handshake_err(
         &self,
-        client: &mut TcpStream,
+        client: &mut ClientStream,
         handshake_output_error: HandShakeOutputError,
     ) -> Result<()> {
         log::error!("Handshake error: {}", handshake_output_error.as_str());
-        client
-            .write_all(HandShakeOutputMsg::Err(handshake_output_error).into())
-            .await?;
+        self.write_response(
+            client,
+            &HandShakeOutputMsg::Err(handshake_output_error).to_bytes(),
+        )
+        .await?;
+        client.flush().await?;
         Ok(())
     }
+}
 
-    #[inline]
-    async fn write_db_err(
-        &self,
-        client: &mut TcpStream,
-        database_output_error: DatabaseOutputError,
-    ) -> Result<()> {
-        log::error!("Database error: {}", database_output_error.as_str());
-        client
-            .write_all(&DatabaseOutputMsg::Err(database_output_error).to_bytes())
-            .await?;
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{MOLECULE_MAX_REQUEST_TAG_LEN, MOLECULE_PROTOCOL_VERSION};
+
+    /// Serves a single client of `molecule` over a loopback connection, returning the client's
+    /// end once the server advertised its capabilities with `INITCONN`.
+    async fn connect(molecule: Arc<Molecule>) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (stream, socket) = listener.accept().await.unwrap();
+            let mut client = BufReader::new(BufWriter::new(stream));
+            let mut session = Session::new(socket);
+
+            molecule
+                .handle_client(&mut client, &mut session)
+                .await
+                .unwrap();
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        assert!(receive(&mut stream).await.starts_with("INITCONN "));
+
+        stream
+    }
+
+    async fn send(stream: &mut TcpStream, message: &str) {
+        write_frame(stream, message.as_bytes()).await.unwrap();
+    }
+
+    async fn receive(stream: &mut TcpStream) -> String {
+        let frame = read_frame(stream, usize::MAX).await.unwrap().unwrap();
+
+        String::from_utf8(frame).unwrap()
+    }
+
+    async fn handshake(molecule: Arc<Molecule>, message: &str) -> String {
+        let mut stream = connect(molecule).await;
+        send(&mut stream, message).await;
+
+        receive(&mut stream).await
+    }
+
+    #[tokio::test]
+    async fn handshake_requires_credentials_once_a_user_exists() {
+        assert_eq!(
+            handshake(Arc::new(Molecule::in_memory()), "OK").await,
+            "READY"
+        );
+
+        let molecule = Molecule::in_memory();
+        molecule
+            .create_user("alice".into(), "secret".into(), "admin".into())
+            .await
+            .unwrap();
+        let molecule = Arc::new(molecule);
+
+        for (message, response) in [
+            ("OK", "ERR auth_required"),
+            ("HELLO 2", "ERR auth_required"),
+            ("HELLO 2 alice", "ERR malformed_auth_str"),
+            ("HELLO 2 alice:wrong", "ERR incorrect_auth_info"),
+            ("OK alice:secret", "READY"),
+            ("HELLO 2 alice:secret", "READY 2"),
+        ] {
+            assert_eq!(
+                handshake(molecule.clone(), message).await,
+                response,
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn echoes_request_tags() {
+        let mut stream = connect(Arc::new(Molecule::in_memory())).await;
+        send(&mut stream, "OK").await;
+        assert_eq!(receive(&mut stream).await, "READY");
+
+        send(&mut stream, "#1 COLLECTIONS_LIST").await;
+        send(&mut stream, "#2 BOGUS").await;
+        send(&mut stream, "COLLECTIONS_LIST").await;
+
+        assert_eq!(receive(&mut stream).await, "#1 []");
+        assert!(
+            receive(&mut stream)
+                .await
+                .starts_with("#2 ERR invalid_input ")
+        );
+        assert_eq!(receive(&mut stream).await, "[]");
+
+        let tag = "x".repeat(MOLECULE_MAX_REQUEST_TAG_LEN);
+        send(&mut stream, &format!("#{}y COLLECTIONS_LIST", tag)).await;
+
+        assert!(
+            receive(&mut stream)
+                .await
+                .starts_with(&format!("#{} ERR invalid_input ", tag))
+        );
+    }
+
+    #[tokio::test]
+    async fn negotiates_protocol_versions() {
+        let molecule = Arc::new(Molecule::in_memory());
+
+        for (message, response) in [
+            ("OK", "READY".to_string()),
+            ("HELLO 1", "READY 1".to_string()),
+            (
+                &format!("HELLO {}", MOLECULE_PROTOCOL_VERSION),
+                format!("READY {}", MOLECULE_PROTOCOL_VERSION),
+            ),
+            ("HELLO", "ERR invalid_handshake".to_string()),
+            ("HELLO 0", "ERR unsupported_version".to_string()),
+            (
+                &format!("HELLO {}", MOLECULE_PROTOCOL_VERSION + 1),
+                "ERR unsupported_version".to_string(),
+            ),
+            ("HELLO two", "ERR malformed_request".to_string()),
+            ("HELLO -1", "ERR malformed_request".to_string()),
+            ("HI", "ERR invalid_handshake_msg".to_string()),
+        ] {
+            assert_eq!(
+                handshake(molecule.clone(), message).await,
+                response,
+                "{}",
+                message
+            );
+        }
     }
 }
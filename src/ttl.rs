@@ -0,0 +1,191 @@
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::core::collection::MoleculeCoreCollectionApi;
+use crate::core::error::CoreError;
+use crate::core::record::MoleculeCoreRecordsApi;
+use crate::core::storage::Storage;
+use crate::molecule::Molecule;
+
+pub trait MoleculeTtlApi {
+    async fn start_ttl_monitor(self: Arc<Self>);
+    async fn expire_records(&self) -> Result<usize>;
+}
+
+impl MoleculeTtlApi for Molecule {
+    /// Removes expired records every `ttl_sweep_interval` for as long as the database runs. A
+    /// failed sweep is logged and retried on the next tick.
+    async fn start_ttl_monitor(self: Arc<Self>) {
+        let mut interval = time::interval(self.ttl_sweep_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.expire_records().await {
+                log::error!("TTL sweep failed: {}", e);
+            }
+        }
+    }
+
+    /// Deletes every record expired by a TTL index of any collection, returning how many were
+    /// deleted. TTL indexes are found from the stored index definitions, so collections without
+    /// one are never loaded, and collections deleted during the sweep are skipped.
+    async fn expire_records(&self) -> Result<usize> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+        let mut expired = 0;
+
+        for collection in self.list_collections().await? {
+            let lock = self.collection_lock(&collection.collection_id);
+            let guard = lock.read().await;
+            let definitions = self
+                .storage
+                .index_definitions(&collection.collection_id)
+                .await?;
+
+            drop(guard);
+
+            for definition in definitions {
+                let Some(filter) = definition.expired_filter(now)? else {
+                    continue;
+                };
+
+                let outcome = match self
+                    .delete_records(collection.collection_id.clone(), filter)
+                    .await
+                {
+                    Ok(outcome) => outcome,
+                    Err(err)
+                        if matches!(
+                            err.downcast_ref::<CoreError>(),
+                            Some(CoreError::CollectionNotFound(_))
+                        ) =>
+                    {
+                        break;
+                    }
+                    Err(err) => return Err(err),
+                };
+
+                if outcome.deleted > 0 {
+                    log::info!(
+                        "Expired {} record(s) of collection {} through TTL index {}.",
+                        outcome.deleted,
+                        collection.collection_id,
+                        definition.name
+                    );
+                }
+
+                expired += outcome.deleted;
+            }
+        }
+
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::core::index::{IndexOptions, MoleculeCoreIndexApi};
+    use crate::core::query::QueryOptions;
+    use crate::core::record::InsertOptions;
+
+    fn contents(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    async fn record_ids(molecule: &Molecule, collection_id: &str) -> Vec<String> {
+        molecule
+            .get_records(collection_id.to_string(), QueryOptions::default())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r["_id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn expires_records_through_ttl_indexes() {
+        let molecule = Molecule::in_memory();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+        let old = now - 120.0;
+
+        let sessions = molecule.create_collection("sessions".into()).await.unwrap();
+        let tokens = molecule.create_collection("tokens".into()).await.unwrap();
+        let logs = molecule.create_collection("logs".into()).await.unwrap();
+
+        molecule
+            .create_index(
+                sessions.clone(),
+                vec!["seen_at".into()],
+                IndexOptions {
+                    expire_after_secs: Some(60),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        molecule
+            .create_index(
+                tokens.clone(),
+                vec!["seen_at".into()],
+                IndexOptions {
+                    expire_after_secs: Some(60),
+                    partial: Some(json!({"kind": "temporary"})),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let records = vec![
+            contents(json!({"_id": "expired", "seen_at": old})),
+            contents(json!({"_id": "fresh", "seen_at": now})),
+            contents(json!({"_id": "missing"})),
+            contents(json!({"_id": "not_a_time", "seen_at": "yesterday"})),
+            contents(json!({"_id": "earliest_expired", "seen_at": [now, old]})),
+        ];
+
+        molecule
+            .create_records(sessions.clone(), records, InsertOptions::default())
+            .await
+            .unwrap();
+        molecule
+            .create_records(
+                tokens.clone(),
+                vec![
+                    contents(json!({"_id": "temporary", "kind": "temporary", "seen_at": old})),
+                    contents(json!({"_id": "permanent", "kind": "permanent", "seen_at": old})),
+                ],
+                InsertOptions::default(),
+            )
+            .await
+            .unwrap();
+        molecule
+            .create_record(
+                logs.clone(),
+                contents(json!({"_id": "log", "seen_at": old})),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(molecule.expire_records().await.unwrap(), 3);
+        assert_eq!(
+            record_ids(&molecule, &sessions).await,
+            vec!["fresh", "missing", "not_a_time"]
+        );
+        assert_eq!(record_ids(&molecule, &tokens).await, vec!["permanent"]);
+        assert_eq!(record_ids(&molecule, &logs).await, vec!["log"]);
+        assert_eq!(molecule.expire_records().await.unwrap(), 0);
+    }
+}